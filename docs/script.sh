@@ -557,17 +557,27 @@ export cdp_mgr=$(echo $result | grep "Component: "| awk -F "Component: " '{print
 export dse=$(echo $result | grep "Resource: " | awk -F "Resource: " '{if (NR==1) print $2}' | awk -F " " '{print $1}')
 export cdp=$(echo $result | grep "Resource: " | awk -F "Resource: " '{if (NR==2) print $2}' | awk -F " " '{print $1}')
 export flash=$(echo $result | grep "Resource: " | awk -F "Resource: " '{if (NR==3) print $2}' | awk -F " " '{print $1}')
+export timelock=$(echo $result | grep "Component: "| awk -F "Component: " '{print $2}' | awk -F " " '{if (NR==6) print $1}')
 export xrd="resource_sim1tknxxxxxxxxxradxrdxxxxxxxxx009923554798xxxxxxxxxakj8n3"
 
 resim run < ./docs/replace_holder.sh docs/transactions/set_price.rtm
 export xrd="resource_sim1tknxxxxxxxxxradxrdxxxxxxxxx009923554798xxxxxxxxxakj8n3"
-result=$(resim run < ./docs/replace_holder.sh docs/transactions/new_xrd_pool.rtm)
+# the pools are created through the timelock: queue, wait for the delay (288 epochs in new_protocol.rtm), execute.
+export eta_epoch=288
+resim run < ./docs/replace_holder.sh docs/transactions/new_xrd_pool.rtm
+resim run < ./docs/replace_holder.sh docs/transactions/new_usdt_pool.rtm
+resim run < ./docs/replace_holder.sh docs/transactions/new_usdc_pool.rtm
+resim set-current-epoch 288
+export action_id=1
+result=$(resim run < ./docs/replace_holder.sh docs/transactions/execute.rtm)
 export xrd_pool=$(echo $result | grep "Component: "| awk -F "Component: " '{print $2}' | awk -F " " '{print $1}')
 export dx_xrd=$(echo $result | grep "Resource: " | awk -F "Resource: " '{if (NR==1) print $2}' | awk -F " " '{print $1}')
-result=$(resim run < ./docs/replace_holder.sh docs/transactions/new_usdt_pool.rtm)
+export action_id=2
+result=$(resim run < ./docs/replace_holder.sh docs/transactions/execute.rtm)
 export usdt_pool=$(echo $result | grep "Component: "| awk -F "Component: " '{print $2}' | awk -F " " '{print $1}')
 export dx_usdt=$(echo $result | grep "Resource: " | awk -F "Resource: " '{if (NR==1) print $2}')
-result=$(resim run < ./docs/replace_holder.sh docs/transactions/new_usdc_pool.rtm)
+export action_id=3
+result=$(resim run < ./docs/replace_holder.sh docs/transactions/execute.rtm)
 export usdc_pool=$(echo $result | grep "Component: "| awk -F "Component: " '{print $2}' | awk -F " " '{print $1}')
 export dx_usdc=$(echo $result | grep "Resource: " | awk -F "Resource: " '{if (NR==1) print $2}')
 
@@ -607,10 +617,10 @@ resim show $usdt_pool
 resim show $usdc_pool
 
 
-resim set-current-epoch 2
+resim set-current-epoch 290
 resim set-default-account $p1 $p1_priv $p1_badge
 export quote="usdt"
-export epoch=2
+export epoch=290
 export price1="0.056259787085"
 export quote1=$usdt
 export timestamp1=1700658816
@@ -628,7 +638,7 @@ resim run < ./docs/replace_holder.sh docs/transactions/borrow_variable.rtm
 
 resim set-default-account $p3 $p3_priv $p3_badge
 export quote="usdc"
-export epoch=2
+export epoch=290
 export price1="0.056259787085"
 export quote1=$usdc
 export timestamp1=1700658816
@@ -2,6 +2,7 @@ use scrypto::prelude::*;
 use crate::pools::lending::lend_pool::LendResourcePool;
 use crate::interest::InterestModel;
 use crate::oracle::oracle::PriceOracle;
+use crate::risk::*;
 use crate::utils::*;
 
 #[derive(ScryptoSbor, NonFungibleData)]
@@ -14,6 +15,7 @@ pub struct FlashLoanData{
 #[derive(ScryptoSbor, NonFungibleData)]
 pub struct CollateralDebtPosition{
     pub borrow_token: ResourceAddress,
+    #[mutable]
     pub collateral_token: ResourceAddress,
 
     #[mutable]
@@ -36,7 +38,69 @@ pub struct CollateralDebtPosition{
     #[mutable]
     pub last_update_epoch: u64,
     #[mutable]
-    pub stable_rate: Decimal
+    pub stable_rate: Decimal,
+    // e-mode category the CDP opted into, 0 for none
+    #[mutable]
+    pub emode_category: u8
+}
+
+/// Read-only view of a CDP, the values are quoted in XRD.
+#[derive(ScryptoSbor)]
+pub struct CDPSummary{
+    pub cdp_id: NonFungibleLocalId,
+    pub borrow_token: ResourceAddress,
+    // underlying token of the collateral
+    pub collateral_token: ResourceAddress,
+    pub is_stable: bool,
+    pub stable_rate: Decimal,
+    pub emode_category: u8,
+    // current debt in borrow token, including the accrued interest
+    pub debt_amount: Decimal,
+    // interest accrued over the life of the CDP(debt + total_repay - total_borrow)
+    pub accrued_interest: Decimal,
+    pub debt_value: Decimal,
+    // collateral in underlying token
+    pub collateral_amount: Decimal,
+    pub collateral_value: Decimal,
+    // borrow token that can still be borrowed under the LTV
+    pub max_borrowable: Decimal,
+    // underlying token of the collateral that can be withdrawn under the LTV
+    pub max_withdrawable: Decimal,
+    pub health_factor: Decimal,
+    // collateral price(in XRD) at which the health factor reaches ONE
+    pub liquidation_price: Decimal
+}
+
+/// Read-only view of a lending market.
+#[derive(ScryptoSbor)]
+pub struct MarketInfo{
+    pub underlying_token: ResourceAddress,
+    pub dx_token: ResourceAddress,
+    pub supply_index: Decimal,
+    pub borrow_index: Decimal,
+    pub supply_rate: Decimal,
+    pub variable_borrow_rate: Decimal,
+    pub stable_borrow_rate: Decimal,
+    pub total_supply: Decimal,
+    pub total_variable_borrow: Decimal,
+    pub total_stable_borrow: Decimal,
+    // (variable + stable borrow) / supply
+    pub utilization: Decimal,
+    pub available_liquidity: Decimal,
+    pub insurance_balance: Decimal,
+    pub ltv: Decimal,
+    pub liquidation_threshold: Decimal,
+    pub liquidation_bonus: Decimal,
+    pub ltv_ramp: Option<ParameterRamp>,
+    pub liquidation_threshold_ramp: Option<ParameterRamp>,
+    pub is_isolated: bool,
+    pub isolation_debt_ceiling: Decimal,
+    pub isolated_debts: HashMap<ResourceAddress, Decimal>,
+    pub borrowable_in_isolation: bool,
+    pub emode_category: u8,
+    // ZERO means unlimited
+    pub supply_cap: Decimal,
+    pub borrow_cap: Decimal
 }
 
 #[derive(ScryptoSbor)]
@@ -46,11 +110,125 @@ struct AssetState{
     pub collateral_token: ResourceAddress,
     pub ltv: Decimal,
     pub liquidation_threshold: Decimal,
-    pub liquidation_bonus: Decimal
+    pub liquidation_bonus: Decimal,
+    // scheduled ramps, `ltv` and `liquidation_threshold` hold the targets while the ramps are in progress
+    pub ltv_ramp: Option<ParameterRamp>,
+    pub liquidation_threshold_ramp: Option<ParameterRamp>,
+    // share of the liquidation bonus kept by the protocol as insurance
+    pub liquidation_protocol_fee: Decimal,
+    // base close factor(percent) for liquidation of debt in this asset
+    pub close_factor_percent: Decimal,
+    // positions below this health factor can be liquidated in full
+    pub critical_health_factor: Decimal,
+    // remaining debt below this amount is liquidated in full
+    pub dust_threshold: Decimal,
+    // stable rates can be rebalanced when the supply rate is below this threshold(ZERO disables)
+    pub rebalance_supply_rate_threshold: Decimal,
+    // stable rates can be rebalanced when they are below the current stable rate by more than this delta(ZERO disables)
+    pub rebalance_rate_delta: Decimal,
+    // CDPs backed by isolated collateral can only borrow assets borrowable in isolation(USD stablecoins),
+    // up to one global debt ceiling for the sum of their principal(in USD)
+    pub is_isolated: bool,
+    pub isolation_debt_ceiling: Decimal,
+    // principal backed by this collateral in isolation mode, per borrowed asset
+    pub isolated_debts: HashMap<ResourceAddress, Decimal>,
+    // whether CDPs backed by isolated collateral may borrow this asset
+    pub borrowable_in_isolation: bool,
+    // e-mode category of correlated assets the asset belongs to, 0 for none
+    pub emode_category: u8,
+    // category the asset is leaving, its CDPs in e-mode move to the base parameters along the ramp of the weight(ONE to ZERO)
+    pub emode_exit_category: u8,
+    pub emode_exit_ramp: Option<ParameterRamp>
+}
+
+/// Overrides of the risk parameters for borrowing an asset against a collateral.
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct PairConfig{
+    pub enabled: bool,
+    pub ltv: Decimal,
+    pub liquidation_threshold: Decimal,
+    // maximum debt of the pair(in borrow token), ZERO means unlimited
+    pub debt_ceiling: Decimal,
+    // principal borrowed by the pair(in borrow token), the accrued interest is not tracked
+    pub total_debt: Decimal
+}
+
+/// Category of correlated assets, CDPs whose collateral and debt both belong to it can opt into its parameters.
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct EModeCategory{
+    pub label: String,
+    pub ltv: Decimal,
+    pub liquidation_threshold: Decimal,
+    pub liquidation_bonus: Decimal,
+    // lowering the parameters is ramped, `ltv` and `liquidation_threshold` hold the targets while the ramps are in progress
+    pub ltv_ramp: Option<ParameterRamp>,
+    pub liquidation_threshold_ramp: Option<ParameterRamp>
+}
+
+impl EModeCategory{
+    fn get_ltv(&self) -> Decimal{
+        get_ramped_value(self.ltv, &self.ltv_ramp)
+    }
+
+    fn get_liquidation_threshold(&self) -> Decimal{
+        get_ramped_value(self.liquidation_threshold, &self.liquidation_threshold_ramp)
+    }
+}
+
+fn get_ramped_value(value: Decimal, ramp: &Option<ParameterRamp>) -> Decimal{
+    match ramp {
+        Some(ramp) => ramp.get_value(Runtime::current_epoch().number()),
+        None => value
+    }
+}
+
+fn emit_parameter_changed(res_addr: ResourceAddress, parameter: &str, old_value: ParameterValue, new_value: ParameterValue){
+    Runtime::emit_event(ParameterChangedEvent{
+        res_addr,
+        parameter: parameter.to_owned(),
+        old_value,
+        new_value
+    });
+}
+
+impl AssetState{
+    /// LTV at the current epoch, interpolated while a ramp is in progress.
+    fn get_ltv(&self) -> Decimal{
+        get_ramped_value(self.ltv, &self.ltv_ramp)
+    }
+
+    /// Liquidation threshold at the current epoch, interpolated while a ramp is in progress.
+    fn get_liquidation_threshold(&self) -> Decimal{
+        get_ramped_value(self.liquidation_threshold, &self.liquidation_threshold_ramp)
+    }
+
+    fn is_ramping(&self) -> bool{
+        let epoch = Runtime::current_epoch().number();
+        self.ltv_ramp.as_ref().is_some_and(|ramp| ramp.is_in_progress(epoch))
+        || self.liquidation_threshold_ramp.as_ref().is_some_and(|ramp| ramp.is_in_progress(epoch))
+    }
+
+    /// Weight(ZERO to ONE) of the parameters of the e-mode category for the asset, below ONE while the asset is leaving the category.
+    fn get_emode_weight(&self, emode_category: u8) -> Decimal{
+        if emode_category == self.emode_category {
+            return Decimal::ONE;
+        }
+        if emode_category == self.emode_exit_category {
+            return get_ramped_value(Decimal::ZERO, &self.emode_exit_ramp);
+        }
+        Decimal::ZERO
+    }
+
+    /// Close factor(percent) applied to the debt of a CDP borrowing this asset.
+    /// It becomes 100% when the CDP is critically unhealthy or when the remaining debt would be dust.
+    fn get_close_factor_percent(&self, health_factor: Decimal, debt_amount: Decimal) -> Decimal{
+        get_close_factor_percent(self.close_factor_percent, self.critical_health_factor, self.dust_threshold, health_factor, debt_amount)
+    }
 }
 
 
 #[blueprint]
+#[events(ParameterChangedEvent, EModeCategoryEvent, PairConfigEvent)]
 mod cdp_mgr{
     
     enable_method_auth!{
@@ -58,30 +236,67 @@ mod cdp_mgr{
             admin => updatable_by: [];
             operator => updatable_by: [];
             protocol_caller => updatable_by:[];
+            risk_manager => updatable_by: [admin];
+            treasurer => updatable_by: [admin];
+            guardian => updatable_by: [admin];
         },
         methods{
             new_pool => restrict_to:[operator, OWNER];
-            withdraw_insurance => restrict_to: [operator, OWNER];
-            set_close_factor =>restrict_to: [operator, OWNER];
+            withdraw_insurance => restrict_to: [treasurer, operator, OWNER];
+
+            //risk manager
+            set_ltv => restrict_to: [risk_manager, OWNER];
+            set_liquidation_threshold => restrict_to: [risk_manager, OWNER];
+            set_liquidation_bonus => restrict_to: [risk_manager, OWNER];
+            set_interest_model => restrict_to: [risk_manager, OWNER];
+            ramp_risk_params => restrict_to: [risk_manager, OWNER];
+            // the guardian cancels a ramp directly, a ramp may be shorter than the timelock delay
+            cancel_ramp => restrict_to: [guardian, risk_manager, admin, OWNER];
+            set_insurance_ratio => restrict_to: [risk_manager, OWNER];
+            set_flashloan_fee_ratio => restrict_to: [risk_manager, OWNER];
+            set_close_factor =>restrict_to: [risk_manager, OWNER];
+            set_liquidation_protocol_fee => restrict_to: [risk_manager, OWNER];
+            set_rebalance_threshold => restrict_to: [risk_manager, OWNER];
+            set_caps => restrict_to: [risk_manager, OWNER];
+            set_isolation => restrict_to: [risk_manager, OWNER];
+            set_borrowable_in_isolation => restrict_to: [risk_manager, OWNER];
+            set_emode_category => restrict_to: [risk_manager, OWNER];
+            set_asset_emode_category => restrict_to: [risk_manager, OWNER];
+            set_pair_config => restrict_to: [risk_manager, OWNER];
+            remove_pair_config => restrict_to: [risk_manager, OWNER];
 
             borrow_variable => restrict_to: [protocol_caller, OWNER];
             borrow_stable => restrict_to: [protocol_caller, OWNER];
             extend_borrow => restrict_to: [protocol_caller, OWNER];
             withdraw_collateral => restrict_to:[protocol_caller, OWNER];
             liquidation => restrict_to:[protocol_caller, OWNER];
+            close_cdp => restrict_to:[protocol_caller, OWNER];
+            split_cdp => restrict_to:[protocol_caller, OWNER];
+            merge_cdp => restrict_to:[protocol_caller, OWNER];
+            swap_collateral => restrict_to:[protocol_caller, OWNER];
+            switch_rate_mode => restrict_to:[protocol_caller, OWNER];
+            rebalance_stable_rate => restrict_to:[protocol_caller, OWNER];
+            set_cdp_emode => restrict_to:[protocol_caller, OWNER];
+            repay => restrict_to:[protocol_caller, OWNER];
+            addition_collateral => restrict_to:[protocol_caller, OWNER];
 
             staking_borrow => restrict_to: [protocol_caller, OWNER];
             staking_repay => restrict_to: [protocol_caller, OWNER];
 
-            borrow_flashloan => PUBLIC;
-            repay_flashloan => PUBLIC;
-            supply => PUBLIC;
-            withdraw => PUBLIC;
-            repay => PUBLIC;
-            addition_collateral => PUBLIC;
+            // only through the protocol, so that the pause applies
+            borrow_flashloan => restrict_to: [protocol_caller, OWNER];
+            repay_flashloan => restrict_to: [protocol_caller, OWNER];
+            supply => restrict_to: [protocol_caller, OWNER];
+            withdraw => restrict_to: [protocol_caller, OWNER];
+
             get_underlying_token => PUBLIC;
+            get_collateral_underlying_token => PUBLIC;
             get_cdp_resource_address => PUBLIC;
             get_interest_rate => PUBLIC;
+            get_cdp_summary => PUBLIC;
+            get_market => PUBLIC;
+            get_emode_category => PUBLIC;
+            get_pair_config => PUBLIC;
         }
     }
 
@@ -95,13 +310,15 @@ mod cdp_mgr{
         deposit_asset_map: KeyValueStore<ResourceAddress, ResourceAddress>,
         // vault for each collateral asset(supply token), I.E. dxXRD ==> Vault(dxXRD)
         collateral_vaults: KeyValueStore<ResourceAddress, Vault>,
+        // e-mode categories of correlated assets, I.E. 1 ==> EModeCategory(USD stablecoins)
+        emode_categories: HashMap<u8, EModeCategory>,
+        // risk overrides keyed by (collateral underlying token, borrow token), I.E. (XRD, USDC) ==> PairConfig
+        pair_configs: KeyValueStore<(ResourceAddress, ResourceAddress), PairConfig>,
         // CDP token define
         cdp_res_mgr: ResourceManager,
         // CDP id counter
         cdp_id_counter: u64,
         self_cmp_addr: ComponentAddress,
-        // close factor for liquidation
-        close_factor_percent: Decimal,
         /// flashloan NFT resource manager
         transient_nft_res_mgr: ResourceManager,
         // flashloan NFT counter
@@ -116,6 +333,9 @@ mod cdp_mgr{
             admin_rule: AccessRule, 
             pool_mgr_rule: AccessRule,
             caller_rule: AccessRule,
+            risk_manager_rule: AccessRule,
+            treasurer_rule: AccessRule,
+            guardian_rule: AccessRule,
             price_oracle: Global<PriceOracle>
         )->(Global<CollateralDebtManager>, ResourceAddress){
             let (address_reservation, address) = Runtime::allocate_component_address(CollateralDebtManager::blueprint_id());
@@ -160,10 +380,11 @@ mod cdp_mgr{
             let component = Self{
                 pools: HashMap::new(),
                 states: HashMap::new(),
+                emode_categories: HashMap::new(),
+                pair_configs: KeyValueStore::new(),
                 deposit_asset_map: KeyValueStore::new(),
                 collateral_vaults: KeyValueStore::new(),
                 self_cmp_addr: address,
-                close_factor_percent: Decimal::from(50),
                 cdp_id_counter: 0u64,
                 transient_id_counter: 0u64,
                 cdp_vault: NonFungibleVault::new(cdp_res_mgr.address()),
@@ -177,6 +398,9 @@ mod cdp_mgr{
                 admin => admin_rule.clone();
                 operator => pool_mgr_rule.clone();
                 protocol_caller => caller_rule.clone();
+                risk_manager => risk_manager_rule;
+                treasurer => treasurer_rule;
+                guardian => guardian_rule;
             }
             )
             .globalize();
@@ -220,7 +444,22 @@ mod cdp_mgr{
                 collateral_token: dx_token_addr,
                 ltv,
                 liquidation_threshold,
-                liquidation_bonus
+                liquidation_bonus,
+                ltv_ramp: None,
+                liquidation_threshold_ramp: None,
+                liquidation_protocol_fee: Decimal::ZERO,
+                close_factor_percent: Decimal::from(50),
+                critical_health_factor: Decimal::ZERO,
+                dust_threshold: Decimal::ZERO,
+                rebalance_supply_rate_threshold: Decimal::ZERO,
+                rebalance_rate_delta: Decimal::ZERO,
+                is_isolated: false,
+                isolation_debt_ceiling: Decimal::ZERO,
+                isolated_debts: HashMap::new(),
+                borrowable_in_isolation: false,
+                emode_category: 0u8,
+                emode_exit_category: 0u8,
+                emode_exit_ramp: None
             };
             self.pools.insert(underlying_token_addr, lend_res_pool);
             self.states.insert(underlying_token_addr, asset_state);
@@ -235,7 +474,7 @@ mod cdp_mgr{
             assert!(self.pools.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
             let borrow_bucket = self.staking_borrow_from_pool(underlying_token_addr, borrow_amount, stable_rate);
             //mint cdp
-            let cdp_bucket = self.new_cdp(collateral_token, underlying_token_addr, borrow_amount, collateral_amount, Decimal::ZERO, stable_rate, true).as_non_fungible();
+            let cdp_bucket = self.new_cdp(collateral_token, underlying_token_addr, borrow_amount, collateral_amount, Decimal::ZERO, stable_rate, true, 0u8).as_non_fungible();
             let cdp_nft_id = cdp_bucket.non_fungible_local_id();
             self.cdp_vault.put(cdp_bucket);
             (borrow_bucket, cdp_nft_id)
@@ -267,8 +506,246 @@ mod cdp_mgr{
             lending_pool.get_interest_rate(stable_borrow_amount)
         }
 
-        pub fn set_close_factor(&mut self, new_close_factor: Decimal){
-            self.close_factor_percent = new_close_factor;
+        pub fn set_ltv(&mut self, underlying_token_addr: ResourceAddress, ltv: Decimal){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            assert!(!state.is_ramping(), "A ramp of the risk parameters is in progress.");
+            assert_risk_params(ltv, state.get_liquidation_threshold(), state.liquidation_bonus);
+            emit_parameter_changed(underlying_token_addr, "ltv", ParameterValue::Decimal(state.get_ltv()), ParameterValue::Decimal(ltv));
+            state.ltv = ltv;
+            state.ltv_ramp = None;
+        }
+
+        pub fn set_liquidation_threshold(&mut self, underlying_token_addr: ResourceAddress, liquidation_threshold: Decimal){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            assert!(!state.is_ramping(), "A ramp of the risk parameters is in progress.");
+            assert_risk_params(state.get_ltv(), liquidation_threshold, state.liquidation_bonus);
+            emit_parameter_changed(underlying_token_addr, "liquidation_threshold", ParameterValue::Decimal(state.get_liquidation_threshold()), ParameterValue::Decimal(liquidation_threshold));
+            state.liquidation_threshold = liquidation_threshold;
+            state.liquidation_threshold_ramp = None;
+        }
+
+        pub fn set_liquidation_bonus(&mut self, underlying_token_addr: ResourceAddress, liquidation_bonus: Decimal){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            assert_risk_params(state.get_ltv(), state.get_liquidation_threshold(), liquidation_bonus);
+            // the ramps are linear, so checking the targets covers the whole path
+            assert_risk_params(state.ltv, state.liquidation_threshold, liquidation_bonus);
+            emit_parameter_changed(underlying_token_addr, "liquidation_bonus", ParameterValue::Decimal(state.liquidation_bonus), ParameterValue::Decimal(liquidation_bonus));
+            state.liquidation_bonus = liquidation_bonus;
+        }
+
+        /// Ramps the LTV and the liquidation threshold linearly from the current values to the targets over `ramp_epochs` epochs.
+        pub fn ramp_risk_params(&mut self,
+            underlying_token_addr: ResourceAddress,
+            target_ltv: Decimal,
+            target_liquidation_threshold: Decimal,
+            ramp_epochs: u64
+        ){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert!(ramp_epochs > 0u64, "The ramp must last at least one epoch.");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            assert_risk_params(target_ltv, target_liquidation_threshold, state.liquidation_bonus);
+            let ltv_ramp = ParameterRamp::new(state.get_ltv(), target_ltv, ramp_epochs);
+            let liquidation_threshold_ramp = ParameterRamp::new(state.get_liquidation_threshold(), target_liquidation_threshold, ramp_epochs);
+            emit_parameter_changed(underlying_token_addr, "ltv", ParameterValue::Decimal(ltv_ramp.start_value), ParameterValue::Ramp(ltv_ramp.clone()));
+            emit_parameter_changed(underlying_token_addr, "liquidation_threshold", ParameterValue::Decimal(liquidation_threshold_ramp.start_value), ParameterValue::Ramp(liquidation_threshold_ramp.clone()));
+            state.ltv = target_ltv;
+            state.liquidation_threshold = target_liquidation_threshold;
+            state.ltv_ramp = Some(ltv_ramp);
+            state.liquidation_threshold_ramp = Some(liquidation_threshold_ramp);
+        }
+
+        /// Stops the ramps and keeps the LTV and the liquidation threshold of the current epoch.
+        pub fn cancel_ramp(&mut self, underlying_token_addr: ResourceAddress){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            assert!(state.is_ramping(), "There is no ramp of the risk parameters in progress.");
+            let ltv = state.get_ltv();
+            let liquidation_threshold = state.get_liquidation_threshold();
+            emit_parameter_changed(underlying_token_addr, "ltv", ParameterValue::Ramp(state.ltv_ramp.clone().unwrap()), ParameterValue::Decimal(ltv));
+            emit_parameter_changed(underlying_token_addr, "liquidation_threshold", ParameterValue::Ramp(state.liquidation_threshold_ramp.clone().unwrap()), ParameterValue::Decimal(liquidation_threshold));
+            state.ltv = ltv;
+            state.liquidation_threshold = liquidation_threshold;
+            state.ltv_ramp = None;
+            state.liquidation_threshold_ramp = None;
+        }
+
+        pub fn set_interest_model(&mut self, underlying_token_addr: ResourceAddress, interest_model: InterestModel){
+            assert!(self.pools.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let previous = self.pools.get_mut(&underlying_token_addr).unwrap().set_interest_model(interest_model.clone());
+            self.states.get_mut(&underlying_token_addr).unwrap().interest_model = interest_model.clone();
+            emit_parameter_changed(underlying_token_addr, "interest_model", ParameterValue::InterestModel(previous), ParameterValue::InterestModel(interest_model));
+        }
+
+        pub fn set_insurance_ratio(&mut self, underlying_token_addr: ResourceAddress, insurance_ratio: Decimal){
+            assert!(self.pools.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let previous = self.pools.get_mut(&underlying_token_addr).unwrap().set_insurance_ratio(insurance_ratio);
+            emit_parameter_changed(underlying_token_addr, "insurance_ratio", ParameterValue::Decimal(previous), ParameterValue::Decimal(insurance_ratio));
+        }
+
+        pub fn set_flashloan_fee_ratio(&mut self, underlying_token_addr: ResourceAddress, flashloan_fee_ratio: Decimal){
+            assert!(self.pools.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let previous = self.pools.get_mut(&underlying_token_addr).unwrap().set_flashloan_fee_ratio(flashloan_fee_ratio);
+            emit_parameter_changed(underlying_token_addr, "flashloan_fee_ratio", ParameterValue::Decimal(previous), ParameterValue::Decimal(flashloan_fee_ratio));
+        }
+
+        pub fn set_close_factor(&mut self, 
+            underlying_token_addr: ResourceAddress,
+            close_factor_percent: Decimal,
+            critical_health_factor: Decimal,
+            dust_threshold: Decimal
+        ){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert!(close_factor_percent.is_positive() && close_factor_percent <= Decimal::ONE_HUNDRED, "The close factor must be in (0, 100].");
+            assert!(!critical_health_factor.is_negative() && critical_health_factor <= Decimal::ONE, "The critical health factor must be in [0, 1].");
+            assert!(!dust_threshold.is_negative(), "The dust threshold can not be negative.");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            emit_parameter_changed(underlying_token_addr, "close_factor_percent", ParameterValue::Decimal(state.close_factor_percent), ParameterValue::Decimal(close_factor_percent));
+            emit_parameter_changed(underlying_token_addr, "critical_health_factor", ParameterValue::Decimal(state.critical_health_factor), ParameterValue::Decimal(critical_health_factor));
+            emit_parameter_changed(underlying_token_addr, "dust_threshold", ParameterValue::Decimal(state.dust_threshold), ParameterValue::Decimal(dust_threshold));
+            state.close_factor_percent = close_factor_percent;
+            state.critical_health_factor = critical_health_factor;
+            state.dust_threshold = dust_threshold;
+        }
+
+        pub fn set_liquidation_protocol_fee(&mut self, underlying_token_addr: ResourceAddress, liquidation_protocol_fee: Decimal){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert!(!liquidation_protocol_fee.is_negative() && liquidation_protocol_fee <= Decimal::ONE, "The liquidation protocol fee must be in [0, 1].");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            emit_parameter_changed(underlying_token_addr, "liquidation_protocol_fee", ParameterValue::Decimal(state.liquidation_protocol_fee), ParameterValue::Decimal(liquidation_protocol_fee));
+            state.liquidation_protocol_fee = liquidation_protocol_fee;
+        }
+
+        pub fn set_rebalance_threshold(&mut self, underlying_token_addr: ResourceAddress, supply_rate_threshold: Decimal, rate_delta: Decimal){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert!(!supply_rate_threshold.is_negative() && !rate_delta.is_negative(), "The rebalance thresholds can not be negative.");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            emit_parameter_changed(underlying_token_addr, "rebalance_supply_rate_threshold", ParameterValue::Decimal(state.rebalance_supply_rate_threshold), ParameterValue::Decimal(supply_rate_threshold));
+            emit_parameter_changed(underlying_token_addr, "rebalance_rate_delta", ParameterValue::Decimal(state.rebalance_rate_delta), ParameterValue::Decimal(rate_delta));
+            state.rebalance_supply_rate_threshold = supply_rate_threshold;
+            state.rebalance_rate_delta = rate_delta;
+        }
+
+        /// Sets the supply cap and the borrow cap(in underlying token) of the pool, ZERO means unlimited.
+        pub fn set_caps(&mut self, underlying_token_addr: ResourceAddress, supply_cap: Decimal, borrow_cap: Decimal){
+            assert!(self.pools.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let (previous_supply_cap, previous_borrow_cap) = self.pools.get_mut(&underlying_token_addr).unwrap().set_caps(supply_cap, borrow_cap);
+            emit_parameter_changed(underlying_token_addr, "supply_cap", ParameterValue::Decimal(previous_supply_cap), ParameterValue::Decimal(supply_cap));
+            emit_parameter_changed(underlying_token_addr, "borrow_cap", ParameterValue::Decimal(previous_borrow_cap), ParameterValue::Decimal(borrow_cap));
+        }
+
+        /// Puts the collateral in or out of isolation mode, the debt ceiling(summed over the borrowed stablecoins) must be positive in isolation mode.
+        pub fn set_isolation(&mut self, underlying_token_addr: ResourceAddress, is_isolated: bool, debt_ceiling: Decimal){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert!(!debt_ceiling.is_negative() && (!is_isolated || debt_ceiling.is_positive()), "The debt ceiling must be positive in isolation mode.");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            emit_parameter_changed(underlying_token_addr, "is_isolated", ParameterValue::Bool(state.is_isolated), ParameterValue::Bool(is_isolated));
+            emit_parameter_changed(underlying_token_addr, "isolation_debt_ceiling", ParameterValue::Decimal(state.isolation_debt_ceiling), ParameterValue::Decimal(debt_ceiling));
+            state.is_isolated = is_isolated;
+            state.isolation_debt_ceiling = debt_ceiling;
+        }
+
+        /// Only USD stablecoins should be borrowable in isolation, their principal is summed against the debt ceilings.
+        pub fn set_borrowable_in_isolation(&mut self, underlying_token_addr: ResourceAddress, borrowable: bool){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            emit_parameter_changed(underlying_token_addr, "borrowable_in_isolation", ParameterValue::Bool(state.borrowable_in_isolation), ParameterValue::Bool(borrowable));
+            state.borrowable_in_isolation = borrowable;
+        }
+
+        /// Creates or updates the e-mode category(1-255).
+        /// Lowering the ltv or the liquidation threshold of an existing category is ramped over `ramp_epochs` epochs,
+        /// so that the CDPs in e-mode are not exposed at once.
+        pub fn set_emode_category(&mut self,
+            emode_category: u8,
+            label: String,
+            ltv: Decimal,
+            liquidation_threshold: Decimal,
+            liquidation_bonus: Decimal,
+            ramp_epochs: u64
+        ){
+            assert!(emode_category != 0u8, "The e-mode category 0 is reserved.");
+            assert_risk_params(ltv, liquidation_threshold, liquidation_bonus);
+            let (ltv_ramp, liquidation_threshold_ramp) = match self.emode_categories.get(&emode_category) {
+                Some(previous) if ltv < previous.get_ltv() || liquidation_threshold < previous.get_liquidation_threshold() => {
+                    assert!(ramp_epochs > 0u64, "Lowering the parameters of an e-mode category must be ramped.");
+                    (
+                        Some(ParameterRamp::new(previous.get_ltv(), ltv, ramp_epochs)),
+                        Some(ParameterRamp::new(previous.get_liquidation_threshold(), liquidation_threshold, ramp_epochs))
+                    )
+                },
+                _ => (None, None)
+            };
+            Runtime::emit_event(EModeCategoryEvent{
+                emode_category,
+                label: label.clone(),
+                ltv,
+                liquidation_threshold,
+                liquidation_bonus,
+                ramp_epochs
+            });
+            self.emode_categories.insert(emode_category, EModeCategory{
+                label,
+                ltv,
+                liquidation_threshold,
+                liquidation_bonus,
+                ltv_ramp,
+                liquidation_threshold_ramp
+            });
+        }
+
+        /// Assigns the asset to an e-mode category(0 for none).
+        /// An asset leaving its category keeps a decreasing share of the category parameters over `ramp_epochs` epochs,
+        /// so that its CDPs in e-mode move to the base parameters gradually.
+        pub fn set_asset_emode_category(&mut self, underlying_token_addr: ResourceAddress, emode_category: u8, ramp_epochs: u64){
+            assert!(self.states.get(&underlying_token_addr).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert!(emode_category == 0u8 || self.emode_categories.get(&emode_category).is_some(), "unknow e-mode category.");
+            let state = self.states.get_mut(&underlying_token_addr).unwrap();
+            if state.emode_category != 0u8 && state.emode_category != emode_category {
+                let epoch = Runtime::current_epoch().number();
+                assert!(!state.emode_exit_ramp.as_ref().is_some_and(|ramp| ramp.is_in_progress(epoch)), "The asset is still leaving its previous e-mode category.");
+                assert!(ramp_epochs > 0u64, "Leaving an e-mode category must be ramped.");
+                let exit_ramp = ParameterRamp::new(Decimal::ONE, Decimal::ZERO, ramp_epochs);
+                emit_parameter_changed(underlying_token_addr, "emode_exit_weight", ParameterValue::Decimal(Decimal::ONE), ParameterValue::Ramp(exit_ramp.clone()));
+                state.emode_exit_category = state.emode_category;
+                state.emode_exit_ramp = Some(exit_ramp);
+            }
+            emit_parameter_changed(underlying_token_addr, "emode_category", ParameterValue::Decimal(Decimal::from(state.emode_category)), ParameterValue::Decimal(Decimal::from(emode_category)));
+            state.emode_category = emode_category;
+        }
+
+        /// Configures borrowing `borrow_token` against `collateral_token`(underlying), the debt already tracked for the pair is kept.
+        pub fn set_pair_config(&mut self,
+            collateral_token: ResourceAddress,
+            borrow_token: ResourceAddress,
+            enabled: bool,
+            ltv: Decimal,
+            liquidation_threshold: Decimal,
+            debt_ceiling: Decimal
+        ){
+            assert!(self.states.get(&collateral_token).is_some() && self.states.get(&borrow_token).is_some(), "There is no pool of funds corresponding to the assets!");
+            assert_risk_params(ltv, liquidation_threshold, self.states.get(&collateral_token).unwrap().liquidation_bonus);
+            assert!(!debt_ceiling.is_negative(), "The debt ceiling can not be negative.");
+            let key = (collateral_token, borrow_token);
+            let total_debt = self.pair_configs.get(&key).map(|pair_config| pair_config.total_debt).unwrap_or(Decimal::ZERO);
+            let pair_config = PairConfig{
+                enabled,
+                ltv,
+                liquidation_threshold,
+                debt_ceiling,
+                total_debt
+            };
+            Runtime::emit_event(PairConfigEvent{collateral_token, borrow_token, config: Some(pair_config.clone())});
+            self.pair_configs.insert(key, pair_config);
+        }
+
+        /// Removes the overrides, the pair falls back to the parameters of the collateral.
+        pub fn remove_pair_config(&mut self, collateral_token: ResourceAddress, borrow_token: ResourceAddress){
+            assert!(self.pair_configs.get(&(collateral_token, borrow_token)).is_some(), "The pair is not configured.");
+            self.pair_configs.remove(&(collateral_token, borrow_token));
+            Runtime::emit_event(PairConfigEvent{collateral_token, borrow_token, config: None});
         }
 
         pub fn supply(&mut self, bucket: Bucket) -> Bucket{
@@ -295,13 +772,13 @@ mod cdp_mgr{
         ) -> (Bucket, Bucket){
             let dx_token = dx_bucket.resource_address();
             let dx_amount = dx_bucket.amount();
-            let max_loan_amount = self.get_max_loan_amount(dx_token, dx_amount, borrow_token, borrow_price_in_xrd, collateral_underlying_price_in_xrd, Decimal::ZERO);
-            assert!(borrow_amount <= max_loan_amount, "The amount borrowed exceeds the borrowable quantity of the collateral.");
+            self.get_position_risk(dx_token, dx_amount, borrow_token, borrow_amount, 0u8, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_borrowable();
+            self.increase_tracked_debt(dx_token, borrow_token, borrow_amount);
 
             self.put_collateral_vault(dx_bucket);
             let (borrow_bucket, borrow_normalized_amount) = self.borrow_variable_from_pool(borrow_token, borrow_amount);
             //mint cdp
-            let cdp_bucket = self.new_cdp(dx_token, borrow_token, borrow_amount, dx_amount, borrow_normalized_amount, Decimal::ZERO, false);
+            let cdp_bucket = self.new_cdp(dx_token, borrow_token, borrow_amount, dx_amount, borrow_normalized_amount, Decimal::ZERO, false, 0u8);
             (borrow_bucket, cdp_bucket)
         }
 
@@ -319,38 +796,38 @@ mod cdp_mgr{
         ) -> (Bucket, Bucket){
             let dx_token = dx_bucket.resource_address();
             let dx_amount = dx_bucket.amount();
-            let max_loan_amount = self.get_max_loan_amount(dx_token, dx_amount, borrow_token, borrow_price_in_xrd, collateral_underlying_price_in_xrd, Decimal::ZERO);
-            assert!(borrow_amount <= max_loan_amount, "The amount borrowed exceeds the borrowable quantity of the collateral.");
+            self.get_position_risk(dx_token, dx_amount, borrow_token, borrow_amount, 0u8, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_borrowable();
+            self.increase_tracked_debt(dx_token, borrow_token, borrow_amount);
             
             self.put_collateral_vault(dx_bucket);
             let (borrow_bucket, stable_rate) = self.borrow_stable_from_pool(borrow_token, borrow_amount);
             
             //mint cdp
-            let cdp_bucket = self.new_cdp(dx_token, borrow_token, borrow_amount, dx_amount, Decimal::ZERO, stable_rate, true);
+            let cdp_bucket = self.new_cdp(dx_token, borrow_token, borrow_amount, dx_amount, Decimal::ZERO, stable_rate, true, 0u8);
             (borrow_bucket, cdp_bucket)
         }
 
         fn borrow_stable_from_pool(&mut self, borrow_token: ResourceAddress, borrow_amount:Decimal) -> (Bucket, Decimal){
             let lending_pool = self.pools.get_mut(&borrow_token).unwrap();
+            lending_pool.assert_borrow_cap(borrow_amount);
             let (_variable_rate,stable_rate,_supply_rate) = lending_pool.get_interest_rate(borrow_amount);
             (lending_pool.borrow_stable(borrow_amount, stable_rate), stable_rate)
         }
 
         pub fn extend_borrow(&mut self,
-            cdp: Bucket,
+            cdp_id: NonFungibleLocalId,
             amount: Decimal,
             borrow_price_in_xrd: Decimal,
             collateral_underlying_price_in_xrd: Decimal
-        ) -> (Bucket, Bucket){
-            assert_resource(&cdp.resource_address(), &self.cdp_res_mgr.address());
-            assert!(cdp.as_non_fungible().amount() == Decimal::ONE, "Only one CDP can be processed at a time!");
-            
-            let cdp_id = cdp.as_non_fungible().non_fungible_local_id();
+        ) -> Bucket{
             let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
             let borrow_token = cdp_data.borrow_token;
             let dx_token = cdp_data.collateral_token;
             let dx_amount = cdp_data.collateral_amount;
-            let max_loan_amount = self.get_max_loan_amount(dx_token, dx_amount, borrow_token, borrow_price_in_xrd, collateral_underlying_price_in_xrd, Decimal::ZERO);
+            let borrow_intent = self.get_current_debt(&cdp_data).checked_add(amount).unwrap();
+            info!("extend borrow: {}:{},{}", borrow_token.to_hex(), amount, borrow_intent);
+            self.get_position_risk(dx_token, dx_amount, borrow_token, borrow_intent, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_borrowable();
+            self.increase_tracked_debt(dx_token, borrow_token, amount);
             
             let mut cdp_avg_rate = Decimal::ZERO;
             let mut interest = Decimal::ZERO;
@@ -359,10 +836,8 @@ mod cdp_mgr{
             let borrow_bucket: Bucket = if cdp_data.is_stable {
                 let borrow_pool = self.pools.get_mut(&borrow_token).unwrap();
                 interest = borrow_pool.get_stable_interest(cdp_data.borrow_amount, cdp_data.last_update_epoch, cdp_data.stable_rate);
-                let borrow_intent = cdp_data.borrow_amount.checked_add(interest).unwrap().checked_add(amount).unwrap();
-                info!("exist stable: {}:{},{},{}", borrow_token.to_hex(), cdp_data.borrow_amount, interest, borrow_intent);
-                assert_amount(borrow_intent, max_loan_amount);
                 
+                borrow_pool.assert_borrow_cap(amount);
                 let (_variable_rate, stable_rate, _supply_rate)  = borrow_pool.get_interest_rate(amount);
                 let borrow_bucket = borrow_pool.borrow_stable(amount, stable_rate);
                 cdp_avg_rate = get_weight_rate(cdp_data.borrow_amount.checked_add(interest).unwrap(), cdp_data.stable_rate, amount, stable_rate);
@@ -371,66 +846,44 @@ mod cdp_mgr{
             }
             else{
                 let borrow_pool = self.pools.get_mut(&borrow_token).unwrap();
-                let (_, current_borrow_index) = borrow_pool.get_current_index();
-                let exist_borrow = cdp_data.normalized_borrow.checked_mul(current_borrow_index).unwrap();
-                let borrow_intent = exist_borrow.checked_add(amount).unwrap();
-                info!("exist variable: {}:{}*{},{}", borrow_token.to_hex(), cdp_data.normalized_borrow,current_borrow_index, borrow_intent);
-                assert_amount(borrow_intent, max_loan_amount);
                 let (borrow_bucket, normalized_amount) = borrow_pool.borrow_variable(amount);
                 delta_normalized_amount = normalized_amount;
                 borrow_bucket
             };
             self.update_cdp_data(cdp_data.is_stable, amount, interest, Decimal::ZERO, delta_normalized_amount, cdp_avg_rate, cdp_id, cdp_data);
             
-            (borrow_bucket, cdp)
+            borrow_bucket
         }
 
         pub fn withdraw_collateral(&mut self,
-            cdp: Bucket,
+            cdp_id: NonFungibleLocalId,
             amount: Decimal,
             borrow_price_in_xrd: Decimal,
             collateral_underlying_price_in_xrd: Decimal
-        ) -> (Bucket, Bucket){
-            assert_resource(&cdp.resource_address(), &self.cdp_res_mgr.address());
-            assert!(cdp.as_non_fungible().amount() == Decimal::ONE, "Only one CDP can be processed at a time!");
-            
-            let cdp_id = cdp.as_non_fungible().non_fungible_local_id();
+        ) -> Bucket{
             let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
             let borrow_token = cdp_data.borrow_token;
             let dx_token = cdp_data.collateral_token;
             let dx_amount = cdp_data.collateral_amount;
 
-            self.validate_withdraw_collateral(dx_token, dx_amount, borrow_token, borrow_price_in_xrd, collateral_underlying_price_in_xrd, cdp_data.normalized_borrow, amount);
-
             let divisibility = get_divisibility(dx_token.clone()).unwrap();
-            let underlying_token = self.deposit_asset_map.get(&dx_token).unwrap();
-            let underlying_pool = self.pools.get_mut(&underlying_token).unwrap();
-            let (supply_index, _) = underlying_pool.get_current_index();
+            let underlying_token = *self.deposit_asset_map.get(&dx_token).unwrap();
+            let (supply_index, _) = self.pools.get(&underlying_token).unwrap().get_current_index();
             
             let take_amount = floor(amount.checked_div(supply_index).unwrap(), divisibility);
-            let dx_bucket = self.collateral_vaults.get_mut(&dx_token).unwrap().take(take_amount);
             let normalized_amount = ceil(amount.checked_div(supply_index).unwrap(), divisibility);
-            let underlying_bucket = underlying_pool.remove_liquity(dx_bucket);
+            let remain_collateral = dx_amount.checked_sub(normalized_amount).unwrap();
+            let current_debt = self.get_current_debt(&cdp_data);
+            self.get_position_risk(dx_token, remain_collateral, borrow_token, current_debt, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_collateral_sufficient();
+
+            let dx_bucket = self.collateral_vaults.get_mut(&dx_token).unwrap().take(take_amount);
+            let underlying_bucket = self.pools.get_mut(&underlying_token).unwrap().remove_liquity(dx_bucket);
             info!("amount:{}, take_amount:{}, normalized_amount:{}, underlying_bucket.amount:{}",amount, take_amount, normalized_amount, underlying_bucket.amount());
-            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", dx_amount.checked_sub(normalized_amount).unwrap());
-            (underlying_bucket, cdp)
-        }
-        
-        fn validate_withdraw_collateral(&self, dx_token: ResourceAddress, dx_amount: Decimal,
-            borrow_token: ResourceAddress, borrow_price_in_xrd: Decimal, 
-            collateral_underlying_price_in_xrd: Decimal, cdp_normalized_borrow: Decimal,
-            withdraw_amount: Decimal
-        ){
-            let max_loan_amount = self.get_max_loan_amount(dx_token, dx_amount, borrow_token, borrow_price_in_xrd, collateral_underlying_price_in_xrd, withdraw_amount);
-            let borrow_pool = self.pools.get(&borrow_token).unwrap();
-            let (_supply_index, borrow_index) = borrow_pool.get_current_index();
-            let current_borrow_amount = cdp_normalized_borrow.checked_mul(borrow_index).unwrap();
-            info!("current_borrow_amount:{}, max_loan_amount:{}, withdraw_amount:{}",  current_borrow_amount, max_loan_amount, withdraw_amount);
-            assert!(max_loan_amount >= current_borrow_amount, "Insufficient remaining collateral.");
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", remain_collateral);
+            underlying_bucket
         }
 
-        pub fn addition_collateral(&mut self, id: u64, bucket: Bucket){
-            let cdp_id = NonFungibleLocalId::integer(id);
+        pub fn addition_collateral(&mut self, cdp_id: NonFungibleLocalId, bucket: Bucket){
             let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
             let dx_token = cdp_data.collateral_token;
             
@@ -454,22 +907,23 @@ mod cdp_mgr{
             }
         }
 
-        pub fn repay(&mut self, repay_bucket: Bucket, id: u64) -> (Bucket, Decimal){
-            let cdp_id: NonFungibleLocalId = NonFungibleLocalId::integer(id);
+        pub fn repay(&mut self, repay_bucket: Bucket, cdp_id: NonFungibleLocalId) -> (Bucket, Decimal){
             let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
             let borrow_token = cdp_data.borrow_token;
+            let dx_token = cdp_data.collateral_token;
             assert_resource(&borrow_token, &repay_bucket.resource_address());
             
-            let (bucket, payment_amount) = if cdp_data.is_stable {
+            let (bucket, payment_amount, principal) = if cdp_data.is_stable {
                 let (return_bucket, actual_repay_amount, repay_in_borrow) = self.repay_stable_to_pool(borrow_token, repay_bucket, cdp_data.borrow_amount, cdp_data.stable_rate, cdp_data.last_update_epoch, None);
-                self.update_cdp_after_repay(&cdp_id, cdp_data, actual_repay_amount, repay_in_borrow, Decimal::ZERO, Decimal::ZERO);
-                (return_bucket, actual_repay_amount)
+                let principal = self.update_cdp_after_repay(&cdp_id, cdp_data, actual_repay_amount, repay_in_borrow, Decimal::ZERO, Decimal::ZERO);
+                (return_bucket, actual_repay_amount, principal)
             }
             else{
                 let (return_bucket, actual_repay_amount, repay_normalized_amount) = self.repay_variable_to_pool(borrow_token, repay_bucket, cdp_data.normalized_borrow, None);
-                self.update_cdp_after_repay(&cdp_id, cdp_data, actual_repay_amount, Decimal::ZERO, repay_normalized_amount, Decimal::ZERO);
-                (return_bucket, actual_repay_amount)
+                let principal = self.update_cdp_after_repay(&cdp_id, cdp_data, actual_repay_amount, Decimal::ZERO, repay_normalized_amount, Decimal::ZERO);
+                (return_bucket, actual_repay_amount, principal)
             };
+            self.decrease_tracked_debt(dx_token, borrow_token, principal);
 
             (bucket, payment_amount)
         }
@@ -490,51 +944,343 @@ mod cdp_mgr{
             (bucket, actual_repay_amount, repay_in_borrow)
         }
 
+        /// Repays the whole outstanding debt of the CDP, releases all of its collateral and burns the CDP.
+        /// The collateral is returned as dx token, or as underlying token when `withdraw_underlying` is set.
+        pub fn close_cdp(&mut self, cdp: Bucket, repay_bucket: Bucket, withdraw_underlying: bool) -> (Bucket, Bucket, Decimal){
+            assert_resource(&cdp.resource_address(), &self.cdp_res_mgr.address());
+            assert!(cdp.as_non_fungible().amount() == Decimal::ONE, "Only one CDP can be processed at a time!");
+
+            let cdp_id = cdp.as_non_fungible().non_fungible_local_id();
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let borrow_token = cdp_data.borrow_token;
+            let dx_token = cdp_data.collateral_token;
+            assert_resource(&borrow_token, &repay_bucket.resource_address());
+
+            let (refund_bucket, payment_amount) = if cdp_data.is_stable && cdp_data.borrow_amount.is_positive() {
+                let (return_bucket, actual_repay_amount, repay_in_borrow) = self.repay_stable_to_pool(borrow_token, repay_bucket, cdp_data.borrow_amount, cdp_data.stable_rate, cdp_data.last_update_epoch, None);
+                assert!(repay_in_borrow == cdp_data.borrow_amount, "The repay bucket does not cover the whole debt of the CDP.");
+                (return_bucket, actual_repay_amount)
+            }
+            else if !cdp_data.is_stable && cdp_data.normalized_borrow.is_positive() {
+                let (return_bucket, actual_repay_amount, repay_normalized_amount) = self.repay_variable_to_pool(borrow_token, repay_bucket, cdp_data.normalized_borrow, None);
+                assert!(repay_normalized_amount == cdp_data.normalized_borrow, "The repay bucket does not cover the whole debt of the CDP.");
+                (return_bucket, actual_repay_amount)
+            }
+            else{
+                (repay_bucket, Decimal::ZERO)
+            };
+            info!("close cdp: payment_amount:{}, collateral_amount:{}", payment_amount, cdp_data.collateral_amount);
+            self.decrease_tracked_debt(dx_token, borrow_token, cdp_data.borrow_amount);
+
+            let dx_bucket = self.collateral_vaults.get_mut(&dx_token).unwrap().take(cdp_data.collateral_amount);
+            let collateral_bucket = if withdraw_underlying {
+                let underlying_token = *self.deposit_asset_map.get(&dx_token).unwrap();
+                let underlying_pool = self.pools.get_mut(&underlying_token).unwrap();
+                underlying_pool.remove_liquity(dx_bucket)
+            } else {
+                dx_bucket
+            };
+            self.cdp_res_mgr.burn(cdp);
+
+            (collateral_bucket, refund_bucket, payment_amount)
+        }
+
+        /// Moves `collateral_amount`(dx token) and `debt_amount` of the current debt from the CDP into a new CDP.
+        pub fn split_cdp(&mut self,
+            cdp_id: NonFungibleLocalId,
+            collateral_amount: Decimal,
+            debt_amount: Decimal,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ) -> Bucket{
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let borrow_token = cdp_data.borrow_token;
+            let dx_token = cdp_data.collateral_token;
+            assert!(collateral_amount.is_positive() && collateral_amount < cdp_data.collateral_amount, "The collateral to split must be positive and less than the CDP collateral.");
+            assert!(!debt_amount.is_negative(), "The debt to split can not be negative.");
+
+            let current_debt = self.get_current_debt(&cdp_data);
+            let (split_normalized_borrow, split_borrow_amount) = if cdp_data.is_stable {
+                (Decimal::ZERO, debt_amount)
+            }
+            else{
+                let (_, borrow_index) = self.pools.get(&borrow_token).unwrap().get_current_index();
+                let split_normalized_borrow = if debt_amount == current_debt { cdp_data.normalized_borrow } else { debt_amount.checked_div(borrow_index).unwrap() };
+                // the borrow amount of a variable CDP is split in the same proportion as its normalized borrow
+                let split_borrow_amount = if cdp_data.normalized_borrow.is_zero() { Decimal::ZERO } else {
+                    cdp_data.borrow_amount.checked_mul(split_normalized_borrow).unwrap().checked_div(cdp_data.normalized_borrow).unwrap()
+                };
+                (split_normalized_borrow, split_borrow_amount)
+            };
+            assert!(debt_amount <= current_debt, "The debt to split exceeds the current debt of the CDP.");
+            let remain_debt = current_debt.checked_sub(debt_amount).unwrap();
+            let remain_collateral = cdp_data.collateral_amount.checked_sub(collateral_amount).unwrap();
+            info!("split cdp: current_debt:{}, debt_amount:{}, collateral_amount:{}", current_debt, debt_amount, collateral_amount);
+
+            self.get_position_risk(dx_token, remain_collateral, borrow_token, remain_debt, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_collateral_sufficient();
+            self.get_position_risk(dx_token, collateral_amount, borrow_token, debt_amount, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_collateral_sufficient();
+
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", remain_collateral);
+            if cdp_data.is_stable {
+                // the accrued interest is capitalized, both CDPs keep accruing at the same stable rate from now on.
+                self.pools.get_mut(&borrow_token).unwrap().restructure_stable(vec![(cdp_data.borrow_amount, cdp_data.stable_rate)], current_debt, cdp_data.stable_rate);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", remain_debt);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "last_update_epoch", Runtime::current_epoch().number());
+            }
+            else{
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "normalized_borrow", cdp_data.normalized_borrow.checked_sub(split_normalized_borrow).unwrap());
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", cdp_data.borrow_amount.checked_sub(split_borrow_amount).unwrap());
+            }
+
+            // the borrow and repay history is split in the same proportion as the debt, so that both CDPs keep their accrued interest
+            let split_ratio = if current_debt.is_zero() { Decimal::ZERO } else { debt_amount.checked_div(current_debt).unwrap() };
+            let split_total_borrow = cdp_data.total_borrow.checked_mul(split_ratio).unwrap();
+            let split_total_repay = cdp_data.total_repay.checked_mul(split_ratio).unwrap();
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "total_borrow", cdp_data.total_borrow.checked_sub(split_total_borrow).unwrap());
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "total_repay", cdp_data.total_repay.checked_sub(split_total_repay).unwrap());
+
+            let new_cdp = self.new_cdp(dx_token, borrow_token, split_borrow_amount, collateral_amount, split_normalized_borrow, cdp_data.stable_rate, cdp_data.is_stable, cdp_data.emode_category);
+            let new_cdp_id = new_cdp.as_non_fungible().non_fungible_local_id();
+            self.cdp_res_mgr.update_non_fungible_data(&new_cdp_id, "total_borrow", split_total_borrow);
+            self.cdp_res_mgr.update_non_fungible_data(&new_cdp_id, "total_repay", split_total_repay);
+            new_cdp
+        }
+
+        /// Merges the other CDP into the CDP and burns the other CDP.
+        /// Both CDPs must have the same collateral, borrow token and interest mode.
+        pub fn merge_cdp(&mut self,
+            cdp_id: NonFungibleLocalId,
+            other_cdp: Bucket,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ){
+            assert_resource(&other_cdp.resource_address(), &self.cdp_res_mgr.address());
+            assert!(other_cdp.as_non_fungible().amount() == Decimal::ONE, "Only one CDP can be processed at a time!");
+            let other_cdp_id = other_cdp.as_non_fungible().non_fungible_local_id();
+            assert!(other_cdp_id != cdp_id, "Can not merge the CDP into itself.");
+
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let other_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&other_cdp_id);
+            assert!(
+                cdp_data.borrow_token == other_data.borrow_token
+                && cdp_data.collateral_token == other_data.collateral_token
+                && cdp_data.is_stable == other_data.is_stable,
+                "The CDPs to merge must have the same collateral, borrow token and interest mode."
+            );
+            let borrow_token = cdp_data.borrow_token;
+            let dx_token = cdp_data.collateral_token;
+            let collateral_amount = cdp_data.collateral_amount.checked_add(other_data.collateral_amount).unwrap();
+
+            let current_debt = if cdp_data.is_stable {
+                let debt = self.get_current_debt(&cdp_data);
+                let other_debt = self.get_current_debt(&other_data);
+                let current_debt = debt.checked_add(other_debt).unwrap();
+                let stable_rate = if current_debt.is_zero() { Decimal::ZERO } else {
+                    get_weight_rate(debt, cdp_data.stable_rate, other_debt, other_data.stable_rate)
+                };
+                info!("merge stable cdp: {}@{} + {}@{} ==> {}@{}", debt, cdp_data.stable_rate, other_debt, other_data.stable_rate, current_debt, stable_rate);
+                self.pools.get_mut(&borrow_token).unwrap().restructure_stable(
+                    vec![(cdp_data.borrow_amount, cdp_data.stable_rate), (other_data.borrow_amount, other_data.stable_rate)],
+                    current_debt, stable_rate
+                );
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", current_debt);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "stable_rate", stable_rate);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "last_update_epoch", Runtime::current_epoch().number());
+                current_debt
+            }
+            else{
+                let (_, borrow_index) = self.pools.get(&borrow_token).unwrap().get_current_index();
+                let normalized_borrow = cdp_data.normalized_borrow.checked_add(other_data.normalized_borrow).unwrap();
+                info!("merge variable cdp: {} + {} ==> {}", cdp_data.normalized_borrow, other_data.normalized_borrow, normalized_borrow);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "normalized_borrow", normalized_borrow);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", cdp_data.borrow_amount.checked_add(other_data.borrow_amount).unwrap());
+                normalized_borrow.checked_mul(borrow_index).unwrap()
+            };
+            self.get_position_risk(dx_token, collateral_amount, borrow_token, current_debt, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_collateral_sufficient();
+
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", collateral_amount);
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "total_borrow", cdp_data.total_borrow.checked_add(other_data.total_borrow).unwrap());
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "total_repay", cdp_data.total_repay.checked_add(other_data.total_repay).unwrap());
+            self.cdp_res_mgr.burn(other_cdp);
+        }
+
+        /// Replaces the whole collateral of the CDP with the bucket(underlying or dx token)
+        /// and returns the previous collateral as dx token.
+        pub fn swap_collateral(&mut self,
+            cdp_id: NonFungibleLocalId,
+            bucket: Bucket,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ) -> Bucket{
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let borrow_token = cdp_data.borrow_token;
+            let old_dx_token = cdp_data.collateral_token;
+
+            let bucket_token = bucket.resource_address();
+            let new_dx_bucket = if self.deposit_asset_map.get(&bucket_token).is_some() {
+                bucket
+            } else {
+                assert!(self.pools.get(&bucket_token).is_some(), "There is no pool of funds corresponding to the assets!");
+                self.pools.get_mut(&bucket_token).unwrap().add_liquity(bucket)
+            };
+            let new_dx_token = new_dx_bucket.resource_address();
+            let new_dx_amount = new_dx_bucket.amount();
+            assert!(new_dx_token != old_dx_token, "The new collateral must differ from the current CDP collateral.");
+
+            let current_debt = self.get_current_debt(&cdp_data);
+            self.get_position_risk(new_dx_token, new_dx_amount, borrow_token, current_debt, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_collateral_sufficient();
+            // the tracked principal moves to the new collateral
+            self.decrease_tracked_debt(old_dx_token, borrow_token, cdp_data.borrow_amount);
+            self.increase_tracked_debt(new_dx_token, borrow_token, cdp_data.borrow_amount);
+
+            self.put_collateral_vault(new_dx_bucket);
+            let old_dx_bucket = self.collateral_vaults.get_mut(&old_dx_token).unwrap().take(cdp_data.collateral_amount);
+            info!("swap collateral: {}|{} ==> {}|{}", Runtime::bech32_encode_address(old_dx_token), cdp_data.collateral_amount, Runtime::bech32_encode_address(new_dx_token), new_dx_amount);
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_token", new_dx_token);
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", new_dx_amount);
+            old_dx_bucket
+        }
+
+        /// Opts the CDP into the e-mode category(0 opts out), the CDP must stay healthy under the new parameters.
+        pub fn set_cdp_emode(&mut self,
+            cdp_id: NonFungibleLocalId,
+            emode_category: u8,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ){
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            if emode_category != 0u8 {
+                assert!(self.emode_categories.get(&emode_category).is_some(), "unknow e-mode category.");
+                let underlying_token = *self.deposit_asset_map.get(&cdp_data.collateral_token).unwrap();
+                assert!(
+                    self.states.get(&underlying_token).unwrap().emode_category == emode_category
+                    && self.states.get(&cdp_data.borrow_token).unwrap().emode_category == emode_category,
+                    "The collateral and the debt of the CDP must belong to the e-mode category."
+                );
+            }
+            let current_debt = self.get_current_debt(&cdp_data);
+            self.get_position_risk(cdp_data.collateral_token, cdp_data.collateral_amount, cdp_data.borrow_token, current_debt, emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd).assert_collateral_sufficient();
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "emode_category", emode_category);
+        }
+
+        /// Switches the interest mode of the CDP between variable and stable.
+        /// Returns the new mode, the debt amount and the stable rate(ZERO for variable).
+        pub fn switch_rate_mode(&mut self, cdp_id: NonFungibleLocalId) -> (bool, Decimal, Decimal){
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let borrow_pool = self.pools.get_mut(&cdp_data.borrow_token).unwrap();
+            let epoch_at = Runtime::current_epoch().number();
+            if cdp_data.is_stable {
+                assert!(cdp_data.borrow_amount.is_positive(), "There is no debt in the CDP to switch.");
+                let (debt_amount, normalized_amount) = borrow_pool.switch_to_variable(cdp_data.borrow_amount, cdp_data.stable_rate, cdp_data.last_update_epoch);
+                info!("switch to variable: {}@{} ==> {}|{}", cdp_data.borrow_amount, cdp_data.stable_rate, debt_amount, normalized_amount);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "is_stable", false);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "normalized_borrow", normalized_amount);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", debt_amount);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "stable_rate", Decimal::ZERO);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "last_update_epoch", 0u64);
+                (false, debt_amount, Decimal::ZERO)
+            }
+            else{
+                assert!(cdp_data.normalized_borrow.is_positive(), "There is no debt in the CDP to switch.");
+                let (debt_amount, stable_rate) = borrow_pool.switch_to_stable(cdp_data.normalized_borrow);
+                info!("switch to stable: {} ==> {}@{}", cdp_data.normalized_borrow, debt_amount, stable_rate);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "is_stable", true);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "normalized_borrow", Decimal::ZERO);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", debt_amount);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "stable_rate", stable_rate);
+                self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "last_update_epoch", epoch_at);
+                (true, debt_amount, stable_rate)
+            }
+        }
+
+        /// Resets the stable rate of the CDP to the current stable rate of the pool.
+        /// Allowed when the supply rate of the pool is below the threshold,
+        /// or the stable rate of the CDP is far below the current stable rate.
+        /// Returns the previous rate, the new rate and the debt amount.
+        pub fn rebalance_stable_rate(&mut self, cdp_id: NonFungibleLocalId) -> (Decimal, Decimal, Decimal){
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let borrow_token = cdp_data.borrow_token;
+            assert!(cdp_data.is_stable && cdp_data.borrow_amount.is_positive(), "Only the stable CDP with debt can be rebalanced.");
+            // staking earning CDPs are repaid by the fixed unstake claim, a higher rate would leave debt behind.
+            assert!(cdp_data.collateral_token != XRD, "The staking CDP can not be rebalanced.");
+
+            let borrow_state = self.states.get(&borrow_token).unwrap();
+            let supply_rate_threshold = borrow_state.rebalance_supply_rate_threshold;
+            let rate_delta = borrow_state.rebalance_rate_delta;
+
+            let borrow_pool = self.pools.get_mut(&borrow_token).unwrap();
+            let (_variable_rate, current_stable_rate, supply_rate) = borrow_pool.get_interest_rate(Decimal::ZERO);
+            let is_supply_rate_low = supply_rate_threshold.is_positive() && supply_rate < supply_rate_threshold;
+            let is_rate_far_below = rate_delta.is_positive() && cdp_data.stable_rate.checked_add(rate_delta).unwrap() < current_stable_rate;
+            info!("rebalance: supply_rate:{}/{}, stable_rate:{}/{}, delta:{}", supply_rate, supply_rate_threshold, cdp_data.stable_rate, current_stable_rate, rate_delta);
+            assert!(
+                cdp_data.stable_rate < current_stable_rate && (is_supply_rate_low || is_rate_far_below),
+                "The CDP does not meet the conditions of stable rate rebalancing."
+            );
+
+            let (debt_amount, stable_rate) = borrow_pool.rebalance_stable(cdp_data.borrow_amount, cdp_data.stable_rate, cdp_data.last_update_epoch);
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "borrow_amount", debt_amount);
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "stable_rate", stable_rate);
+            self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "last_update_epoch", Runtime::current_epoch().number());
+            (cdp_data.stable_rate, stable_rate, debt_amount)
+        }
+
         pub fn liquidation(&mut self,
             debt_bucket: Bucket,
             debt_to_cover: Decimal,
             cdp_id: NonFungibleLocalId,
             borrow_price_in_xrd: Decimal, 
             underlying_token: ResourceAddress,
-            collateral_underlying_price_in_xrd: Decimal
-        ) -> (Bucket, Bucket){
+            collateral_underlying_price_in_xrd: Decimal,
+            receive_dx: bool
+        ) -> (Bucket, Bucket, Decimal, Decimal){
             let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
             let borrow_token = cdp_data.borrow_token;
             let dx_token = cdp_data.collateral_token;
             let dx_amount = cdp_data.collateral_amount;
 
-            let (actual_debt_to_liquidate,release_collateral_to_liqiudate) = self.get_liquidate_debt_and_collateral(
-                borrow_price_in_xrd, collateral_underlying_price_in_xrd, debt_to_cover,
-                borrow_token, underlying_token.clone(), cdp_data.borrow_amount, cdp_data.normalized_borrow, cdp_data.collateral_amount, cdp_data.is_stable,
-                cdp_data.stable_rate, cdp_data.last_update_epoch
+            let (actual_debt_to_liquidate, release_collateral_to_liqiudate, protocol_fee_collateral, liquidator_bonus) = self.get_liquidate_debt_and_collateral(
+                &cdp_data, borrow_price_in_xrd, collateral_underlying_price_in_xrd, debt_to_cover
             );
-            info!("actual_debt_to_liquidate:{}, release_collateral_to_liqiudate:{}", actual_debt_to_liquidate, release_collateral_to_liqiudate);
+            info!("actual_debt_to_liquidate:{}, release_collateral_to_liqiudate:{}, protocol_fee_collateral:{}", actual_debt_to_liquidate, release_collateral_to_liqiudate, protocol_fee_collateral);
 
             let repay_amount = debt_bucket.amount();
             assert!(repay_amount >= actual_debt_to_liquidate, "the debt bucket does not cover to debt of the CDP.");
             
-            let (bucket, actual_repay_amount) = if cdp_data.is_stable{
+            let (bucket, actual_repay_amount, principal) = if cdp_data.is_stable{
                 let (return_bucket, actual_repay_amount, repay_in_borrow) = self.repay_stable_to_pool(borrow_token, debt_bucket, cdp_data.borrow_amount, cdp_data.stable_rate, cdp_data.last_update_epoch, Some(actual_debt_to_liquidate));
                 info!("stable debt: actual_repay_amount:{}, bucket:{}", actual_repay_amount, return_bucket.amount());
-                self.update_cdp_after_repay(&cdp_id, cdp_data, actual_repay_amount, repay_in_borrow, Decimal::ZERO, Decimal::ZERO);
-                (return_bucket, actual_repay_amount)
+                let principal = self.update_cdp_after_repay(&cdp_id, cdp_data, actual_repay_amount, repay_in_borrow, Decimal::ZERO, Decimal::ZERO);
+                (return_bucket, actual_repay_amount, principal)
             }
             else{
                 let (return_bucket, actual_repay_amount, repay_normalized_amount) = self.repay_variable_to_pool(borrow_token, debt_bucket, cdp_data.normalized_borrow, Some(actual_debt_to_liquidate));
                 info!("variable debt: repay_normalized_amount:{}, bucket:{}, actual_repay_amount:{}", repay_normalized_amount, return_bucket.amount(), actual_repay_amount);
-                self.update_cdp_after_repay(&cdp_id, cdp_data, actual_debt_to_liquidate, Decimal::ZERO, repay_normalized_amount, Decimal::ZERO);
-                (return_bucket, actual_repay_amount)
+                let principal = self.update_cdp_after_repay(&cdp_id, cdp_data, actual_debt_to_liquidate, Decimal::ZERO, repay_normalized_amount, Decimal::ZERO);
+                (return_bucket, actual_repay_amount, principal)
             };
             assert!(actual_repay_amount == actual_debt_to_liquidate, "The actual repay amount dose not matches debt to liquidate.");
+            self.decrease_tracked_debt(dx_token, borrow_token, principal);
 
             info!("debt_bucket:{}", bucket.amount());
             let underlying_pool = self.pools.get_mut(&underlying_token).unwrap();
             let mut vault = self.collateral_vaults.get_mut(&dx_token).unwrap();
             info!("underlying:{}, dx:{}, dx_vault:{}", Runtime::bech32_encode_address(underlying_token), Runtime::bech32_encode_address(dx_token),vault.amount());
-            let release_underlying_bucket = underlying_pool.remove_liquity(vault.take(release_collateral_to_liqiudate));
-            info!("underlying(collateral) amount:{}", release_underlying_bucket.amount());
+            let mut release_dx_bucket = vault.take(release_collateral_to_liqiudate);
+            let protocol_fee = if protocol_fee_collateral.is_positive() {
+                underlying_pool.collect_insurance(release_dx_bucket.take(protocol_fee_collateral))
+            } else {
+                Decimal::ZERO
+            };
+            // the dx token is handed over when asked for, or when the pool is short of underlying liquidity
+            let release_bucket = if receive_dx || underlying_pool.get_redemption_value(release_dx_bucket.amount()) > underlying_pool.get_available() {
+                release_dx_bucket
+            } else {
+                underlying_pool.remove_liquity(release_dx_bucket)
+            };
+            info!("{}(collateral) amount:{}, liquidator_bonus:{}, protocol_fee:{}", Runtime::bech32_encode_address(release_bucket.resource_address()), release_bucket.amount(), liquidator_bonus, protocol_fee);
             self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", dx_amount.checked_sub(release_collateral_to_liqiudate).unwrap());
-            (release_underlying_bucket, bucket)
+            (release_bucket, bucket, liquidator_bonus, protocol_fee)
 
         }
 
@@ -583,6 +1329,15 @@ mod cdp_mgr{
             self.deposit_asset_map.get(&dx_token).unwrap().clone()
         }
 
+        /// Returns the underlying token of a supported collateral, which could be either the dx token or the underlying token itself.
+        pub fn get_collateral_underlying_token(&self, res_addr: ResourceAddress) -> ResourceAddress{
+            if self.deposit_asset_map.get(&res_addr).is_some() {
+                return self.deposit_asset_map.get(&res_addr).unwrap().clone();
+            }
+            assert!(self.pools.get(&res_addr).is_some(), "unknow resource address.");
+            res_addr
+        }
+
         pub fn get_cdp_resource_address(&self, cdp_id: NonFungibleLocalId)->(ResourceAddress, ResourceAddress){
             let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
             let borrow_token = cdp_data.borrow_token;
@@ -592,39 +1347,139 @@ mod cdp_mgr{
             (borrow_token, underlying_token.clone())
         }
 
+        pub fn get_cdp_summary(&self,
+            cdp_id: NonFungibleLocalId,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ) -> CDPSummary{
+            let cdp_data = self.cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+            let underlying_token = *self.deposit_asset_map.get(&cdp_data.collateral_token).unwrap();
+            let (ltv, liquidation_threshold, _) = self.get_risk_params(underlying_token, cdp_data.borrow_token, cdp_data.emode_category);
+            let risk = self.get_cdp_risk(&cdp_data, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+
+            let accrued_interest = risk.debt_amount.checked_add(cdp_data.total_repay).unwrap().checked_sub(cdp_data.total_borrow).unwrap();
+            let max_borrowable = if risk.borrow_capacity > risk.debt_amount {
+                risk.borrow_capacity.checked_sub(risk.debt_amount).unwrap()
+            } else {
+                Decimal::ZERO
+            };
+            let max_withdrawable = if risk.debt_amount.is_zero() {
+                risk.collateral_amount
+            } else if collateral_underlying_price_in_xrd.is_positive() {
+                // debt.value / (collateral.price * ltv)
+                let required_collateral = risk.debt_value.checked_div(
+                    collateral_underlying_price_in_xrd.checked_mul(ltv).unwrap()
+                ).unwrap();
+                if risk.collateral_amount > required_collateral {
+                    floor_by_resource(underlying_token, risk.collateral_amount.checked_sub(required_collateral).unwrap())
+                } else {
+                    Decimal::ZERO
+                }
+            } else {
+                Decimal::ZERO
+            };
+            // debt.value / (collateral.amount * liquidation_threshold)
+            let liquidation_collateral = risk.collateral_amount.checked_mul(liquidation_threshold).unwrap();
+            let liquidation_price = if risk.debt_amount.is_positive() && liquidation_collateral.is_positive() {
+                risk.debt_value.checked_div(liquidation_collateral).unwrap()
+            } else {
+                Decimal::ZERO
+            };
+
+            CDPSummary{
+                cdp_id,
+                borrow_token: cdp_data.borrow_token,
+                collateral_token: underlying_token,
+                is_stable: cdp_data.is_stable,
+                stable_rate: cdp_data.stable_rate,
+                emode_category: cdp_data.emode_category,
+                debt_amount: risk.debt_amount,
+                accrued_interest: if accrued_interest.is_negative() { Decimal::ZERO } else { accrued_interest },
+                debt_value: risk.debt_value,
+                collateral_amount: risk.collateral_amount,
+                collateral_value: risk.collateral_value,
+                max_borrowable,
+                max_withdrawable,
+                health_factor: risk.health_factor,
+                liquidation_price
+            }
+        }
+
+        pub fn get_market(&self, underlying_token: ResourceAddress) -> MarketInfo{
+            assert!(self.pools.get(&underlying_token).is_some(), "There is no pool of funds corresponding to the assets!");
+            let pool = self.pools.get(&underlying_token).unwrap();
+            let state = self.states.get(&underlying_token).unwrap();
+            let (supply_index, borrow_index) = pool.get_current_index();
+            let (variable_borrow_rate, stable_borrow_rate, supply_rate) = pool.get_interest_rate(Decimal::ZERO);
+            let total_supply = pool.get_deposit_share_quantity().checked_mul(supply_index).unwrap();
+            let total_variable_borrow = pool.get_variable_share_quantity().checked_mul(borrow_index).unwrap();
+            let total_stable_borrow = pool.get_stable_loan_value();
+            let (supply_cap, borrow_cap) = pool.get_caps();
+            let utilization = if total_supply.is_zero() { Decimal::ZERO } else {
+                total_variable_borrow.checked_add(total_stable_borrow).unwrap().checked_div(total_supply).unwrap()
+            };
+
+            MarketInfo{
+                underlying_token,
+                dx_token: state.collateral_token,
+                supply_index,
+                borrow_index,
+                supply_rate,
+                variable_borrow_rate,
+                stable_borrow_rate,
+                total_supply,
+                total_variable_borrow,
+                total_stable_borrow,
+                utilization,
+                available_liquidity: pool.get_available(),
+                insurance_balance: pool.get_insurance_balance(),
+                ltv: state.get_ltv(),
+                liquidation_threshold: state.get_liquidation_threshold(),
+                liquidation_bonus: state.liquidation_bonus,
+                ltv_ramp: state.ltv_ramp.clone(),
+                liquidation_threshold_ramp: state.liquidation_threshold_ramp.clone(),
+                is_isolated: state.is_isolated,
+                isolation_debt_ceiling: state.isolation_debt_ceiling,
+                isolated_debts: state.isolated_debts.clone(),
+                borrowable_in_isolation: state.borrowable_in_isolation,
+                emode_category: state.emode_category,
+                supply_cap,
+                borrow_cap
+            }
+        }
+
+        pub fn get_emode_category(&self, emode_category: u8) -> Option<EModeCategory>{
+            self.emode_categories.get(&emode_category).cloned()
+        }
+
+        pub fn get_pair_config(&self, collateral_token: ResourceAddress, borrow_token: ResourceAddress) -> Option<PairConfig>{
+            self.pair_configs.get(&(collateral_token, borrow_token)).map(|pair_config| pair_config.clone())
+        }
+
         fn get_liquidate_debt_and_collateral(&self,
+            cdp_data: &CollateralDebtPosition,
             debt_price: Decimal,
             collateral_underlying_price: Decimal,
-            debt_to_cover: Decimal,
-            borrow_token: ResourceAddress,
-            underlying_token: ResourceAddress,
-            borrow_amount: Decimal,
-            normalized_borrow: Decimal,
-            collateral_amount: Decimal,
-            is_stable: bool,
-            stable_rate: Decimal,
-            last_update_epoch: u64
-        ) -> (Decimal, Decimal){
-            let underlying_pool = self.pools.get(&underlying_token).unwrap();
-            let debt_pool = self.pools.get(&borrow_token).unwrap();
+            debt_to_cover: Decimal
+        ) -> (Decimal, Decimal, Decimal, Decimal){
+            let borrow_token = cdp_data.borrow_token;
+            let collateral_amount = cdp_data.collateral_amount;
+            let underlying_token = *self.deposit_asset_map.get(&cdp_data.collateral_token).unwrap();
             let underlying_state = self.states.get(&underlying_token).unwrap();
-            let liquidation_threshold = underlying_state.liquidation_threshold;
-            let liquidation_bonus = underlying_state.liquidation_bonus;
-
-            let underlying_amount = underlying_pool.get_redemption_value(collateral_amount);
-            let debt_amount = if is_stable {
-                debt_pool.get_stable_interest(borrow_amount, last_update_epoch, stable_rate)
-            }else{
-                debt_pool.get_variable_interest(normalized_borrow)
-            };
-            let underlying_value = underlying_amount.checked_mul(collateral_underlying_price).unwrap();
-            let health_factor = underlying_value.checked_mul(liquidation_threshold).unwrap()
-            .checked_div(debt_amount.checked_mul(debt_price).unwrap()).unwrap();
-            assert!(health_factor <= Decimal::ONE, "Health factor is not below the threshold");
+            let (_, _, liquidation_bonus) = self.get_risk_params(underlying_token, borrow_token, cdp_data.emode_category);
+            let liquidation_protocol_fee = underlying_state.liquidation_protocol_fee;
+
+            let risk = self.get_cdp_risk(cdp_data, debt_price, collateral_underlying_price);
+            assert!(risk.is_liquidatable(), "Health factor is not below the threshold");
+            let debt_amount = risk.debt_amount;
+            let health_factor = risk.health_factor;
+            let underlying_amount = risk.collateral_amount;
+            let underlying_value = risk.collateral_value;
 
             let collateral_to_underlying_index = underlying_amount.checked_div(collateral_amount).unwrap();
-            let max_to_liquidate = precent_mul(debt_amount, self.close_factor_percent);
-            info!("debt_amount: {}, max_to_liquidate:{}",debt_amount, max_to_liquidate);
+            let close_factor_percent = self.states.get(&borrow_token).unwrap().get_close_factor_percent(health_factor, debt_amount);
+            let max_to_liquidate = precent_mul(debt_amount, close_factor_percent);
+            info!("debt_amount: {}, health_factor:{}, close_factor:{}, max_to_liquidate:{}",debt_amount, health_factor, close_factor_percent, max_to_liquidate);
             let mut actual_to_liquidate = if debt_to_cover.is_positive() && max_to_liquidate > debt_to_cover {debt_to_cover} else{max_to_liquidate};
             // debt.amount * debt.price * (1+liquidation_bonus) / underlying.price
             let mut underlying_to_liquidate = actual_to_liquidate.checked_mul(debt_price).unwrap().checked_mul(
@@ -640,13 +1495,145 @@ mod cdp_mgr{
                 info!("underlying_to_liquidate:{}, underlying_amount:{} actual_to_liquidate:{}", underlying_to_liquidate, underlying_amount, actual_to_liquidate);
             };
 
+            let (protocol_fee_collateral, liquidator_bonus) = split_liquidation_bonus(
+                underlying_to_liquidate,
+                liquidation_bonus,
+                liquidation_protocol_fee,
+                collateral_to_underlying_index,
+                get_divisibility(underlying_state.collateral_token).unwrap()
+            );
+
             (
                 actual_to_liquidate, 
-                underlying_to_liquidate.checked_div(collateral_to_underlying_index).unwrap()
+                underlying_to_liquidate.checked_div(collateral_to_underlying_index).unwrap(),
+                protocol_fee_collateral,
+                liquidator_bonus
             )
             
         }
 
+        /// Tracks the new debt against the isolation mode of the collateral and the configuration of the pair,
+        /// enforcing their allow-lists and debt ceilings.
+        fn increase_tracked_debt(&mut self, dx_token: ResourceAddress, borrow_token: ResourceAddress, amount: Decimal){
+            let underlying_token = *self.deposit_asset_map.get(&dx_token).unwrap();
+            if let Some(mut pair_config) = self.pair_configs.get_mut(&(underlying_token, borrow_token)) {
+                assert!(pair_config.enabled, "Borrowing the asset against the collateral is disabled.");
+                let pair_debt = pair_config.total_debt.checked_add(amount).unwrap();
+                info!("pair debt: {}/{}", pair_debt, pair_config.debt_ceiling);
+                assert!(pair_config.debt_ceiling.is_zero() || pair_debt <= pair_config.debt_ceiling, "The debt ceiling of the pair has been reached.");
+                pair_config.total_debt = pair_debt;
+            }
+
+            if !self.states.get(&underlying_token).unwrap().is_isolated {
+                return;
+            }
+            assert!(self.states.get(&borrow_token).unwrap().borrowable_in_isolation, "The asset can not be borrowed against isolated collateral.");
+            let state = self.states.get_mut(&underlying_token).unwrap();
+            // the assets borrowable in isolation are stablecoins, so their principal is summed against the ceiling
+            let total_isolated_debt = state.isolated_debts.values().fold(amount, |total, debt| total.checked_add(*debt).unwrap());
+            info!("isolated debt: {}/{}", total_isolated_debt, state.isolation_debt_ceiling);
+            assert!(total_isolated_debt <= state.isolation_debt_ceiling, "The debt ceiling of the isolated collateral has been reached.");
+            let isolated_debt = state.isolated_debts.get(&borrow_token).copied().unwrap_or(Decimal::ZERO).checked_add(amount).unwrap();
+            state.isolated_debts.insert(borrow_token, isolated_debt);
+        }
+
+        /// `amount` is the principal repaid, the interest never counts against the ceilings.
+        fn decrease_tracked_debt(&mut self, dx_token: ResourceAddress, borrow_token: ResourceAddress, amount: Decimal){
+            let underlying_token = *self.deposit_asset_map.get(&dx_token).unwrap();
+            if let Some(mut pair_config) = self.pair_configs.get_mut(&(underlying_token, borrow_token)) {
+                pair_config.total_debt = if amount >= pair_config.total_debt { Decimal::ZERO } else { pair_config.total_debt.checked_sub(amount).unwrap() };
+            }
+            let state = self.states.get_mut(&underlying_token).unwrap();
+            if let Some(isolated_debt) = state.isolated_debts.get_mut(&borrow_token) {
+                *isolated_debt = if amount >= *isolated_debt { Decimal::ZERO } else { isolated_debt.checked_sub(amount).unwrap() };
+            }
+        }
+
+        /// Current debt of the CDP in borrow token, including the accrued interest.
+        fn get_current_debt(&self, cdp_data: &CollateralDebtPosition) -> Decimal{
+            get_current_debt(self.pools.get(&cdp_data.borrow_token).unwrap(), cdp_data)
+        }
+
+        /// Risk parameters(ltv, liquidation_threshold, liquidation_bonus) applied to the collateral when borrowing the borrow token.
+        /// The LTV and liquidation threshold of the pair override those of the collateral, the parameters of the e-mode category
+        /// apply when both assets belong to it, weighted down while one of them is leaving the category.
+        fn get_risk_params(&self, collateral_underlying_token: ResourceAddress, borrow_token: ResourceAddress, emode_category: u8) -> (Decimal, Decimal, Decimal){
+            let underlying_state = self.states.get(&collateral_underlying_token).unwrap();
+            let (ltv, liquidation_threshold, liquidation_bonus) = match self.pair_configs.get(&(collateral_underlying_token, borrow_token)) {
+                Some(pair_config) => (pair_config.ltv, pair_config.liquidation_threshold, underlying_state.liquidation_bonus),
+                None => (underlying_state.get_ltv(), underlying_state.get_liquidation_threshold(), underlying_state.liquidation_bonus)
+            };
+            if emode_category == 0u8 {
+                return (ltv, liquidation_threshold, liquidation_bonus);
+            }
+            let collateral_weight = underlying_state.get_emode_weight(emode_category);
+            let borrow_weight = self.states.get(&borrow_token).unwrap().get_emode_weight(emode_category);
+            let weight = if collateral_weight < borrow_weight { collateral_weight } else { borrow_weight };
+            if weight.is_zero() {
+                return (ltv, liquidation_threshold, liquidation_bonus);
+            }
+            let category = self.emode_categories.get(&emode_category).unwrap();
+            if weight == Decimal::ONE {
+                return (category.get_ltv(), category.get_liquidation_threshold(), category.liquidation_bonus);
+            }
+            // base + (category - base) * weight
+            let interpolate = |base: Decimal, value: Decimal| base.checked_add(value.checked_sub(base).unwrap().checked_mul(weight).unwrap()).unwrap();
+            (
+                interpolate(ltv, category.get_ltv()),
+                interpolate(liquidation_threshold, category.get_liquidation_threshold()),
+                interpolate(liquidation_bonus, category.liquidation_bonus)
+            )
+        }
+
+        fn get_cdp_risk(&self,
+            cdp_data: &CollateralDebtPosition,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ) -> PositionRisk{
+            let current_debt = self.get_current_debt(cdp_data);
+            self.get_position_risk(cdp_data.collateral_token, cdp_data.collateral_amount, cdp_data.borrow_token, current_debt, cdp_data.emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd)
+        }
+
+        ///
+        /// Evaluate the risk of a position holding `dx_amount` of collateral and `debt_amount` of debt.
+        /// |   borrow   |   collateral      |   price(base/quote) | stage                                          |
+        /// | ---------- | ----------------- | ------------------- | ---------------------------------------------- |
+        /// | XRD        | USDC              | XRD/USDC            | borrow=price1.base, collateral=price1.quote    |
+        /// | USDT       | USDC              | XRD/USDC, XRD/USDT  | borrow=price1.quote, collateral=price2.quote   |
+        /// | USDT       | XRD               | XRD/USDT            | borrow=price1.quote, collateral=price1.base    |
+        /// | USDC       | XRD               | XRD/USDC            | borrow=price1.quote, collateral=price1.base    |
+        ///
+        fn get_position_risk(&self,
+            dx_token: ResourceAddress,
+            dx_amount: Decimal,
+            borrow_token: ResourceAddress,
+            debt_amount: Decimal,
+            emode_category: u8,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_price_in_xrd: Decimal
+        ) -> PositionRisk{
+            let collateral_token = *self.deposit_asset_map.get(&dx_token).unwrap();
+            let underlying_pool = self.pools.get(&collateral_token).unwrap();
+            let underlying_amount = underlying_pool.get_redemption_value(dx_amount);
+            let (ltv, liquidation_threshold, _) = self.get_risk_params(collateral_token, borrow_token, emode_category);
+            assert!(ltv > Decimal::ZERO, "Loan to Value(LTV) of the collateral asset equals ZERO!");
+
+            let risk = PositionRisk::new(
+                debt_amount,
+                borrow_price_in_xrd,
+                underlying_amount,
+                collateral_underlying_price_in_xrd,
+                ltv,
+                liquidation_threshold,
+                get_divisibility(borrow_token).unwrap()
+            );
+            info!(
+                "position risk: debt:{}*{}, collateral:{}*{}, ltv:{}, threshold:{}, capacity:{}, health_factor:{}",
+                debt_amount, borrow_price_in_xrd, underlying_amount, collateral_underlying_price_in_xrd, ltv, liquidation_threshold, risk.borrow_capacity, risk.health_factor
+            );
+            risk
+        }
+
         fn update_cdp_after_repay(&mut self, 
             cdp_id: &NonFungibleLocalId,
             cdp_data: CollateralDebtPosition,
@@ -654,12 +1641,19 @@ mod cdp_mgr{
             delta_borrow: Decimal,
             delta_normalized_borrow: Decimal,
             delta_collateral: Decimal
-        ){
+        ) -> Decimal{
             info!("total_repay:{}-{}", cdp_data.total_repay, repay_amount);
             self.cdp_res_mgr.update_non_fungible_data(cdp_id, "total_repay", cdp_data.total_repay.checked_add(repay_amount).unwrap());
+            // the principal repaid, a stable repayment that only covers a part of the interest repays no principal.
+            let mut principal = if delta_borrow.is_positive() { delta_borrow } else { Decimal::ZERO };
             if !cdp_data.is_stable && delta_normalized_borrow != Decimal::ZERO{
                 info!("normalized_borrow:{}-{}", cdp_data.normalized_borrow, delta_normalized_borrow);
                 self.cdp_res_mgr.update_non_fungible_data(cdp_id, "normalized_borrow", cdp_data.normalized_borrow.checked_sub(delta_normalized_borrow).unwrap());
+                // the borrow amount of a variable CDP is repaid in the same proportion as its normalized borrow
+                principal = if delta_normalized_borrow >= cdp_data.normalized_borrow { cdp_data.borrow_amount } else {
+                    cdp_data.borrow_amount.checked_mul(delta_normalized_borrow).unwrap().checked_div(cdp_data.normalized_borrow).unwrap()
+                };
+                self.cdp_res_mgr.update_non_fungible_data(cdp_id, "borrow_amount", cdp_data.borrow_amount.checked_sub(principal).unwrap());
             }
 
             if cdp_data.is_stable && delta_borrow != Decimal::ZERO{
@@ -676,6 +1670,7 @@ mod cdp_mgr{
                 info!("collateral_amount:{}|{}", cdp_data.collateral_amount, delta_collateral);
                 self.cdp_res_mgr.update_non_fungible_data(&cdp_id, "collateral_amount", cdp_data.collateral_amount + delta_collateral);
             }
+            principal
         }
 
         fn update_cdp_data(&mut self,
@@ -712,7 +1707,8 @@ mod cdp_mgr{
             collateral_amount: Decimal,
             borrow_normalized_amount: Decimal,
             cdp_avg_rate: Decimal,
-            is_stable: bool
+            is_stable: bool,
+            emode_category: u8
         ) -> Bucket{
             let epoch_at = if is_stable {Runtime::current_epoch().number()} else{0u64};
             let data = CollateralDebtPosition{
@@ -725,7 +1721,8 @@ mod cdp_mgr{
                 collateral_amount,
                 borrow_amount,
                 is_stable,
-                borrow_token
+                borrow_token,
+                emode_category
             };
             self.cdp_id_counter += 1;
             self.cdp_res_mgr.mint_non_fungible(&NonFungibleLocalId::integer(self.cdp_id_counter), data)
@@ -741,74 +1738,39 @@ mod cdp_mgr{
             }
         }
 
-        // fn get_token(&self, dx_token: ResourceAddress) -> (ResourceAddress, Decimal, Decimal){
-        //     let underlying_token = *self.deposit_asset_map.get(&dx_token).unwrap();
-        //     let underlying_pool = self.get_
-        //     (underlying_token, self)
-        // }
-
-        ///
-        /// Calculate the maximum loan amount based on the provided parameters.                                         
-        /// |   borrow   |   collateral      |   price(base/quote) | stage                                          |
-        /// | ---------- | ----------------- | ------------------- | ---------------------------------------------- |
-        /// | XRD        | USDC              | XRD/USDC            | borrow=price1.base, collateral=price1.quote    |
-        /// | USDT       | USDC              | XRD/USDC, XRD/USDT  | borrow=price1.quote, collateral=price2.quote   |
-        /// | USDT       | XRD               | XRD/USDT            | borrow=price1.quote, collateral=price1.base    |
-        /// | USDC       | XRD               | XRD/USDC            | borrow=price1.quote, collateral=price1.base    |
-        ///
-        fn get_max_loan_amount(&self,
-            dx_token: ResourceAddress,
-            dx_amount: Decimal,
-            borrow_token: ResourceAddress,
-            borrow_price_in_xrd: Decimal,
-            collateral_price_in_xrd: Decimal,
-            remove_amount: Decimal
-        ) -> Decimal {
-            let collateral_token = *self.deposit_asset_map.get(&dx_token).unwrap();
-            let underlying_pool = self.pools.get(&collateral_token).unwrap();
-            let underlying_state = self.states.get(&collateral_token).unwrap();
-            let underlying_amount = underlying_pool.get_redemption_value(dx_amount);
-            let amount = underlying_amount.checked_sub(remove_amount).unwrap();
-            let ltv = underlying_state.ltv;
-            assert!(ltv > Decimal::ZERO, "Loan to Value(LTV) of the collateral asset equals ZERO!");
-            
-            info!(
-                "get_max_loan_amount: {}|{},{}*{}, price:{}/{}, underlying_amount:{}, remove_amount:{}",
-                Runtime::bech32_encode_address(dx_token),dx_amount, amount,ltv,borrow_price_in_xrd,collateral_price_in_xrd, underlying_amount, remove_amount
-            );
+    }
+}
 
 
-            let divisibility = get_divisibility(borrow_token);
-            if ltv.is_zero() || divisibility.is_none() {
-                return Decimal::ZERO;
-            }
+#[derive(ScryptoSbor, Debug, Clone)]
+pub enum ParameterValue{
+    Decimal(Decimal),
+    Bool(bool),
+    InterestModel(InterestModel),
+    Ramp(ParameterRamp)
+}
 
-            if borrow_token == XRD && collateral_price_in_xrd.is_positive(){
-                return floor(collateral_price_in_xrd.checked_mul(amount).unwrap()
-                .checked_mul(ltv).unwrap()
-                .checked_div(borrow_price_in_xrd).unwrap(), divisibility.unwrap());
-            }
-            
-            if borrow_token != XRD && collateral_token != XRD {
-                if borrow_price_in_xrd.is_positive() && collateral_price_in_xrd.is_positive() {
-                    return floor(collateral_price_in_xrd.checked_mul(amount).unwrap()
-                    .checked_mul(ltv).unwrap()
-                    .checked_div(borrow_price_in_xrd).unwrap(), divisibility.unwrap());
-                }
-            }
-            
-            if collateral_token == XRD && borrow_price_in_xrd.is_positive() {
-                return floor(
-                    collateral_price_in_xrd.checked_mul(amount).unwrap()
-                    .checked_mul(ltv).unwrap()
-                    .checked_div(borrow_price_in_xrd).unwrap(),
-                    divisibility.unwrap()
-                );
-            }
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct ParameterChangedEvent{
+    pub res_addr: ResourceAddress,
+    pub parameter: String,
+    pub old_value: ParameterValue,
+    pub new_value: ParameterValue
+}
 
-            Decimal::ZERO
-            
-        }
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct EModeCategoryEvent{
+    pub emode_category: u8,
+    pub label: String,
+    pub ltv: Decimal,
+    pub liquidation_threshold: Decimal,
+    pub liquidation_bonus: Decimal,
+    pub ramp_epochs: u64
+}
 
-    }
-}
\ No newline at end of file
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct PairConfigEvent{
+    pub collateral_token: ResourceAddress,
+    pub borrow_token: ResourceAddress,
+    pub config: Option<PairConfig>
+}
@@ -15,15 +15,18 @@ mod staking_earning {
         roles{
             admin => updatable_by: [];
             operator => updatable_by: [admin];
+            risk_manager => updatable_by: [admin];
+            treasurer => updatable_by: [admin];
+            keeper => updatable_by: [admin];
         },
         methods {
-            set_unstake_epoch_num => restrict_to: [operator, OWNER];
+            set_unstake_epoch_num => restrict_to: [risk_manager, OWNER];
             join => restrict_to: [operator, OWNER];
             claim_xrd => restrict_to: [operator, OWNER];
             redeem => restrict_to: [operator, OWNER];
-            withdraw_relayer_fee => restrict_to: [operator, OWNER];
+            withdraw_relayer_fee => restrict_to: [treasurer, OWNER];
 
-            settle => PUBLIC;
+            settle => restrict_to: [keeper, OWNER];
             
             get_dse_token => PUBLIC;
         }
@@ -47,7 +50,10 @@ mod staking_earning {
             unstake_epoch_num: u64,
             settle_gas: Decimal,
             admin_rule: AccessRule,
-            op_rule: AccessRule
+            op_rule: AccessRule,
+            risk_manager_rule: AccessRule,
+            treasurer_rule: AccessRule,
+            keeper_rule: AccessRule
         ) -> Global<StakingEarning>{
             let (address_reservation, component_address) = Runtime::allocate_component_address(
                 StakingEarning::blueprint_id()
@@ -70,6 +76,9 @@ mod staking_earning {
             .roles(roles! {
                 admin => admin_rule.clone();
                 operator => op_rule.clone();
+                risk_manager => risk_manager_rule;
+                treasurer => treasurer_rule;
+                keeper => keeper_rule;
             })
             .globalize();
             component
@@ -139,7 +148,7 @@ mod staking_earning {
 
         pub fn settle(&mut self, cdp_mgr: Global<CollateralDebtManager>, validator_addr: ComponentAddress, res_addr: ResourceAddress,
             nft_id: NonFungibleLocalId, cdp_id: NonFungibleLocalId, subsidy_opt: Option<Bucket>
-        ){
+        ) -> Bucket{
             assert!(self.claim_nft_map.get(&res_addr).is_some_and(|nft_vault|nft_vault.contains_non_fungible(&nft_id)), "unsupported resource address or the NFT id not exists");
             let res_mgr = ResourceManager::from_address(res_addr);
             let unstake_data = res_mgr.get_non_fungible_data::<UnstakeData>(&nft_id);
@@ -162,9 +171,12 @@ mod staking_earning {
             else{
                 Decimal::ZERO
             };
-            let (bucket, actual_repay_amount, _repay_in_borrow) = cdp_mgr.staking_repay(xrd_bucket, cdp_id.clone());
+            let (mut bucket, actual_repay_amount, _repay_in_borrow) = cdp_mgr.staking_repay(xrd_bucket, cdp_id.clone());
             let return_amount = bucket.amount();
-            if  return_amount > Decimal::ZERO {
+            // the keeper gets back at most the settle gas reserved at claim time, the rest goes to the relayer fee.
+            let reward_amount = if return_amount < self.settle_gas { return_amount } else { self.settle_gas };
+            let keeper_reward = bucket.take_advanced(reward_amount, WithdrawStrategy::Rounded(RoundingMode::ToZero));
+            if  bucket.amount() > Decimal::ZERO {
                 self.relay_vault.put(bucket);
             }
             Runtime::emit_event(SettleEvent {
@@ -177,8 +189,10 @@ mod staking_earning {
                 return_amount,
                 actual_repay_amount,
                 cdp_id,
-                subsidy
-            })
+                subsidy,
+                keeper_reward: keeper_reward.amount()
+            });
+            keeper_reward
         }
 
         pub fn withdraw_relayer_fee(&mut self, amount: Decimal) -> Bucket{
@@ -295,7 +309,8 @@ pub struct SettleEvent{
     return_amount: Decimal,
     actual_repay_amount: Decimal,
     cdp_id: NonFungibleLocalId,
-    subsidy: Decimal
+    subsidy: Decimal,
+    keeper_reward: Decimal
 }
 
 #[derive(ScryptoSbor, ScryptoEvent)]
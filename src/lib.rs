@@ -3,6 +3,9 @@ mod oracle;
 mod validator;
 mod interest;
 mod pools;
-mod cdp;
+pub mod cdp;
+pub mod risk;
+mod timelock;
+mod swap;
 mod earning;
 mod protocol;
@@ -15,14 +15,15 @@ mod oracle{
         roles{
             operator => updatable_by: [];
             admin => updatable_by: [];
+            oracle_manager => updatable_by: [admin];
         },
         methods {
-            //admin
-            set_verify_public_key => restrict_to: [admin, OWNER];
+            //oracle manager
+            set_verify_public_key => restrict_to: [oracle_manager, OWNER];
+            set_validity_period => restrict_to: [oracle_manager, OWNER];
     
             //op
             set_price_quote_in_xrd => restrict_to: [operator, admin];
-            set_validity_period => restrict_to: [operator, admin]; 
     
             //public
             get_price_quote_in_xrd => PUBLIC;
@@ -45,6 +46,7 @@ mod oracle{
             owner_role: OwnerRole,
             op_rule: AccessRule,
             admin_rule: AccessRule,
+            oracle_manager_rule: AccessRule,
             price_signer_pk: String,
             max_diff: u64
         ) -> Global<PriceOracle> {
@@ -60,6 +62,7 @@ mod oracle{
                 roles!(
                     admin => admin_rule;
                     operator => op_rule;
+                    oracle_manager => oracle_manager_rule;
                 )
             )
             .globalize()
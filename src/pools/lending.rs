@@ -19,10 +19,19 @@ mod lend_pool {
             repay_variable => restrict_to: [operator];
             borrow_flashloan => restrict_to:[operator];
             repay_flashloan => restrict_to:[operator];
+            collect_insurance => restrict_to:[operator];
+            switch_to_stable => restrict_to:[operator];
+            switch_to_variable => restrict_to:[operator];
+            rebalance_stable => restrict_to:[operator];
+            restructure_stable => restrict_to:[operator];
+            set_caps => restrict_to:[operator];
+            set_interest_model => restrict_to:[operator];
+            set_insurance_ratio => restrict_to:[operator];
+            set_flashloan_fee_ratio => restrict_to:[operator];
             
             //business method
-            add_liquity => PUBLIC;
-            remove_liquity => PUBLIC; 
+            add_liquity => restrict_to:[operator];
+            remove_liquity => restrict_to:[operator];
 
             // readonly
             get_current_index => PUBLIC;
@@ -36,6 +45,10 @@ mod lend_pool {
             get_redemption_value => PUBLIC;
             get_underlying_value => PUBLIC;
             get_flashloan_fee_ratio => PUBLIC;
+            get_stable_loan_value => PUBLIC;
+            get_insurance_balance => PUBLIC;
+            get_caps => PUBLIC;
+            assert_borrow_cap => PUBLIC;
         }
     }
     
@@ -65,7 +78,12 @@ mod lend_pool {
         
         stable_loan_interest_rate: Decimal,
         stable_loan_amount: Decimal,
-        stable_loan_last_update: u64
+        stable_loan_last_update: u64,
+
+        // maximum total supply(in underlying token), ZERO means unlimited
+        supply_cap: Decimal,
+        // maximum total borrow(in underlying token), ZERO means unlimited
+        borrow_cap: Decimal
 
     }
 
@@ -116,6 +134,8 @@ mod lend_pool {
                 stable_loan_interest_rate: Decimal::ZERO,
                 stable_loan_amount: Decimal::ZERO,
                 stable_loan_last_update: 0u64,
+                supply_cap: Decimal::ZERO,
+                borrow_cap: Decimal::ZERO,
                 vault: Vault::new(underlying_token),
                 insurance_balance: Decimal::ZERO,
                 interest_model,
@@ -143,17 +163,66 @@ mod lend_pool {
             self.vault.take_advanced(amount, WithdrawStrategy::Rounded(RoundingMode::ToZero))
         }
 
+        /// Burns the deposit shares and keeps their underlying value in the insurance balance.
+        pub fn collect_insurance(&mut self, bucket: Bucket) -> Decimal{
+            assert_resource(&bucket.resource_address(), &self.deposit_share_res_mgr.address());
+
+            self.update_index();
+
+            let insurance = self.get_redemption_value(bucket.amount());
+            self.deposit_share_res_mgr.burn(bucket);
+            self.insurance_balance = self.insurance_balance.checked_add(insurance).unwrap();
+
+            self.update_interest_rate();
+            insurance
+        }
+
         pub fn get_underlying_value(&self) -> Decimal{
             let res_mgr = ResourceManager::from_address(self.underlying_token);
             let (supply_index, _) = self.get_current_index();
             res_mgr.total_supply().unwrap().checked_mul(supply_index).unwrap()
         }
 
+        pub fn set_caps(&mut self, supply_cap: Decimal, borrow_cap: Decimal) -> (Decimal, Decimal){
+            assert!(!supply_cap.is_negative() && !borrow_cap.is_negative(), "The caps can not be negative.");
+            let previous = (self.supply_cap, self.borrow_cap);
+            self.supply_cap = supply_cap;
+            self.borrow_cap = borrow_cap;
+            previous
+        }
+
+        /// Checkpoints the indices with the previous model before switching the interest model.
+        pub fn set_interest_model(&mut self, interest_model: InterestModel) -> InterestModel{
+            self.update_index();
+            let previous = self.interest_model.clone();
+            self.interest_model = interest_model;
+            self.update_interest_rate();
+            previous
+        }
+
+        /// Checkpoints the indices with the previous ratio before changing the insurance ratio.
+        pub fn set_insurance_ratio(&mut self, insurance_ratio: Decimal) -> Decimal{
+            assert!(!insurance_ratio.is_negative() && insurance_ratio < Decimal::ONE, "The insurance ratio must be in [0, 1).");
+            self.update_index();
+            let previous = self.insurance_ratio;
+            self.insurance_ratio = insurance_ratio;
+            self.update_interest_rate();
+            previous
+        }
+
+        pub fn set_flashloan_fee_ratio(&mut self, flashloan_fee_ratio: Decimal) -> Decimal{
+            assert!(!flashloan_fee_ratio.is_negative() && flashloan_fee_ratio < Decimal::ONE, "The flashloan fee ratio must be in [0, 1).");
+            let previous = self.flashloan_fee_ratio;
+            self.flashloan_fee_ratio = flashloan_fee_ratio;
+            previous
+        }
+
         pub fn add_liquity(&mut self, bucket: Bucket) -> Bucket{
             assert_resource(&bucket.resource_address(), &self.underlying_token);
             let deposit_amount = bucket.amount();
 
             self.update_index();
+            self.assert_supply_cap(deposit_amount);
             
             self.vault.put(bucket);
             
@@ -190,6 +259,7 @@ mod lend_pool {
             assert_vault_amount(&self.vault, borrow_amount);
             
             self.update_index();
+            self.assert_borrow_cap(borrow_amount);
             
             let variable_share = ceil(
                 borrow_amount.checked_div(self.loan_index).unwrap(), 
@@ -323,6 +393,75 @@ mod lend_pool {
 
         }
 
+        /// Converts a variable debt into a stable debt at the current stable rate.
+        /// Returns the debt amount and the stable rate.
+        pub fn switch_to_stable(&mut self, normalized_amount: Decimal) -> (Decimal, Decimal){
+            self.update_index();
+
+            let debt_amount = ceil_by_resource(self.underlying_token, normalized_amount.checked_mul(self.loan_index).unwrap());
+            self.variable_loan_share_quantity = self.variable_loan_share_quantity.checked_sub(normalized_amount).unwrap();
+
+            let (_variable_rate, stable_rate, _supply_rate) = self.get_interest_rate(debt_amount);
+            self.stable_loan_interest_rate = get_weight_rate(self.stable_loan_amount, self.stable_loan_interest_rate, debt_amount, stable_rate);
+            self.stable_loan_amount = self.stable_loan_amount.checked_add(debt_amount).unwrap();
+
+            self.update_interest_rate();
+
+            (debt_amount, stable_rate)
+        }
+
+        /// Converts a stable debt into a variable debt, the accrued stable interest is capitalized.
+        /// Returns the debt amount and the normalized amount of the variable debt.
+        pub fn switch_to_variable(&mut self, loan_amount: Decimal, rate: Decimal, last_epoch_at: u64) -> (Decimal, Decimal){
+            self.update_index();
+
+            let interest = ceil_by_resource(self.underlying_token, self.get_stable_interest(loan_amount, last_epoch_at, rate));
+            let debt_amount = loan_amount.checked_add(interest).unwrap();
+            self.remove_stable_loan(loan_amount, rate);
+
+            let normalized_amount = ceil(
+                debt_amount.checked_div(self.loan_index).unwrap(),
+                self.deposit_share_res_mgr.resource_type().divisibility().unwrap()
+            );
+            self.variable_loan_share_quantity = self.variable_loan_share_quantity.checked_add(normalized_amount).unwrap();
+
+            self.update_interest_rate();
+
+            (debt_amount, normalized_amount)
+        }
+
+        /// Resets a stable debt to the current stable rate, the accrued stable interest is capitalized.
+        /// Returns the debt amount and the new stable rate.
+        pub fn rebalance_stable(&mut self, loan_amount: Decimal, rate: Decimal, last_epoch_at: u64) -> (Decimal, Decimal){
+            self.update_index();
+
+            let interest = ceil_by_resource(self.underlying_token, self.get_stable_interest(loan_amount, last_epoch_at, rate));
+            let debt_amount = loan_amount.checked_add(interest).unwrap();
+            self.remove_stable_loan(loan_amount, rate);
+
+            let (_variable_rate, stable_rate, _supply_rate) = self.get_interest_rate(debt_amount);
+            self.stable_loan_interest_rate = get_weight_rate(self.stable_loan_amount, self.stable_loan_interest_rate, debt_amount, stable_rate);
+            self.stable_loan_amount = self.stable_loan_amount.checked_add(debt_amount).unwrap();
+
+            self.update_interest_rate();
+
+            (debt_amount, stable_rate)
+        }
+
+        /// Replaces the stable loans of `previous_loans`(loan amount, rate) with one stable loan of `debt_amount` at `rate`,
+        /// used when stable CDPs are split or merged and their accrued interest is capitalized.
+        pub fn restructure_stable(&mut self, previous_loans: Vec<(Decimal, Decimal)>, debt_amount: Decimal, rate: Decimal){
+            self.update_index();
+
+            for (loan_amount, loan_rate) in previous_loans {
+                self.remove_stable_loan(loan_amount, loan_rate);
+            }
+            self.stable_loan_interest_rate = get_weight_rate(self.stable_loan_amount, self.stable_loan_interest_rate, debt_amount, rate);
+            self.stable_loan_amount = self.stable_loan_amount.checked_add(debt_amount).unwrap();
+
+            self.update_interest_rate();
+        }
+
         pub fn borrow_flashloan(&mut self, amount: Decimal) -> Bucket {
             assert!(self.vault.amount() >= amount, "Insufficient vault amount!");
             self.vault.take_advanced(amount, WithdrawStrategy::Rounded(RoundingMode::ToZero))
@@ -441,7 +580,43 @@ mod lend_pool {
             self.variable_loan_interest_rate = variable_rate;
         }
 
-        fn get_stable_loan_value(&self) -> Decimal{
+        fn remove_stable_loan(&mut self, loan_amount: Decimal, rate: Decimal){
+            if loan_amount >= self.stable_loan_amount {
+                self.stable_loan_amount = Decimal::ZERO;
+                self.stable_loan_interest_rate = Decimal::ZERO;
+            }
+            else{
+                let previous_debt = self.stable_loan_amount.checked_mul(self.stable_loan_interest_rate).unwrap();
+                self.stable_loan_amount = self.stable_loan_amount.checked_sub(loan_amount).unwrap();
+                self.stable_loan_interest_rate = previous_debt.checked_sub(
+                    loan_amount.checked_mul(rate).unwrap()
+                ).unwrap().checked_div(
+                    self.stable_loan_amount
+                ).unwrap();
+            }
+        }
+
+        fn assert_supply_cap(&self, deposit_amount: Decimal){
+            if self.supply_cap.is_positive() {
+                let total_supply = self.get_deposit_share_quantity().checked_mul(self.deposit_index).unwrap().checked_add(deposit_amount).unwrap();
+                info!("supply cap: {}/{}", total_supply, self.supply_cap);
+                assert!(total_supply <= self.supply_cap, "The supply cap of the pool has been reached.");
+            }
+        }
+
+        /// The staking borrow of the CDP manager is not capped, so the stable borrows are checked by the caller.
+        pub fn assert_borrow_cap(&self, borrow_amount: Decimal){
+            if self.borrow_cap.is_positive() {
+                let (_, loan_index) = self.get_current_index();
+                let total_borrow = self.variable_loan_share_quantity.checked_mul(loan_index).unwrap()
+                    .checked_add(self.get_stable_loan_value()).unwrap()
+                    .checked_add(borrow_amount).unwrap();
+                info!("borrow cap: {}/{}", total_borrow, self.borrow_cap);
+                assert!(total_borrow <= self.borrow_cap, "The borrow cap of the pool has been reached.");
+            }
+        }
+
+        pub fn get_stable_loan_value(&self) -> Decimal{
             let delta_epoch = Runtime::current_epoch().number() - self.stable_loan_last_update;
             if delta_epoch <= 0u64{
                 return self.stable_loan_amount;
@@ -467,6 +642,14 @@ mod lend_pool {
             self.flashloan_fee_ratio
         }
 
+        pub fn get_insurance_balance(&self) -> Decimal{
+            self.insurance_balance
+        }
+
+        pub fn get_caps(&self) -> (Decimal, Decimal){
+            (self.supply_cap, self.borrow_cap)
+        }
+
         pub fn get_deposit_share_quantity(&self) -> Decimal{
             self.deposit_share_res_mgr.total_supply().unwrap()
         }
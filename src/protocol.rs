@@ -1,37 +1,68 @@
 use scrypto::prelude::*;
 use crate::interest::InterestModel;
 use crate::oracle::oracle::PriceOracle;
-// use crate::cdp::CollateralDebtPosition;
+use crate::cdp::CollateralDebtPosition;
 use crate::cdp::FlashLoanData;
+use crate::cdp::{CDPSummary, MarketInfo};
 use crate::cdp::cdp_mgr::CollateralDebtManager;
 use crate::earning::staking_earning::StakingEarning;
+use crate::swap::{adapter_quote, adapter_swap};
+use crate::timelock::timelock::TimeLock;
+use crate::utils::{ceil_by_resource, floor_by_resource};
 use crate::validator::keeper::validator_keeper::ValidatorKeeper;
 
 
 #[blueprint]
-#[events(SupplyEvent, WithdrawEvent, CreateCDPEvent, ExtendBorrowEvent, AdditionCollateralEvent, WithdrawCollateralEvent, RepayEvent, LiquidationEvent, FlashLoanEvent)]
+#[events(SetPausedEvent, SupplyEvent, WithdrawEvent, CreateCDPEvent, ExtendBorrowEvent, AdditionCollateralEvent, WithdrawCollateralEvent, RepayEvent, CloseCDPEvent, SplitCDPEvent, MergeCDPEvent, SwapCollateralEvent, SwitchRateModeEvent, RebalanceStableRateEvent, SetCDPEModeEvent, LiquidationEvent, FlashLoanEvent, SetSwapAdapterEvent, SetKeeperRewardEvent, OpenLeveragedEvent, FlashLiquidationEvent)]
 mod dexian_protocol{
 
     enable_method_auth! {
         roles{
             admin => updatable_by: [];
             operator => updatable_by: [admin];
+            treasurer => updatable_by: [admin];
+            guardian => updatable_by: [admin];
+            keeper => updatable_by: [admin];
         },
         methods {
             // pool
             new_pool => restrict_to: [admin, OWNER];
-            withdraw_insurance => restrict_to: [operator, OWNER];
+            withdraw_insurance => restrict_to: [treasurer, OWNER];
+            withdraw_keeper_fund => restrict_to: [treasurer, OWNER];
+
+            //keeper
+            set_keeper_reward => restrict_to: [admin, OWNER];
+            fund_keeper_reward => PUBLIC;
+
+            //swap adapter
+            set_swap_adapter => restrict_to: [admin, OWNER];
+
+            //guardian
+            pause => restrict_to: [guardian, admin, OWNER];
+            unpause => restrict_to: [admin, OWNER];
 
             //lending
             supply => PUBLIC;
             withdraw => PUBLIC;
             borrow_variable => PUBLIC;
             borrow_stable => PUBLIC;
+            supply_and_borrow => PUBLIC;
+            open_leveraged => PUBLIC;
             extend_borrow => PUBLIC;
             withdraw_collateral => PUBLIC;
             repay => PUBLIC;
+            repay_with_collateral => PUBLIC;
             addition_collateral => PUBLIC;
+            close_cdp => PUBLIC;
+            split_cdp => PUBLIC;
+            merge_cdp => PUBLIC;
+            swap_collateral => PUBLIC;
+            switch_rate_mode => PUBLIC;
+            rebalance_stable_rate => restrict_to: [keeper, OWNER];
+            set_emode => PUBLIC;
             liquidation => PUBLIC;
+            flash_liquidate => PUBLIC;
+            liquidate_batch => PUBLIC;
 
             //flashloan
             // migrate_cdp => PUBLIC;
@@ -41,6 +72,12 @@ mod dexian_protocol{
             //staking earning
             join => PUBLIC;
             redeem => PUBLIC;
+
+            //query
+            get_cdp_summary => PUBLIC;
+            get_market => PUBLIC;
+            get_swap_adapters => PUBLIC;
+            quote_swap => PUBLIC;
         }
     }
     
@@ -52,6 +89,12 @@ mod dexian_protocol{
         cdp_res_addr: ResourceAddress,
         admin_rule: AccessRule,
         op_rule: AccessRule,
+        paused: bool,
+        // approved DEX adapters used by the swap based operations
+        swap_adapters: HashSet<ComponentAddress>,
+        // XRD paid to the keeper for each stable rate rebalancing
+        keeper_reward: Decimal,
+        keeper_vault: Vault,
     }
 
     impl DeXianProtocol{
@@ -63,21 +106,41 @@ mod dexian_protocol{
             price_signer_pk: String, 
             price_validity_ms: u64,
             unstake_epoch_num: u64,
-            settle_gas: Decimal
+            settle_gas: Decimal,
+            risk_manager_rule: AccessRule,
+            oracle_manager_rule: AccessRule,
+            treasurer_rule: AccessRule,
+            guardian_rule: AccessRule,
+            keeper_rule: AccessRule,
+            timelock_delay_epochs: u64
         ) -> (
             Global<DeXianProtocol>,
             Global<PriceOracle>,
             Global<StakingEarning>, 
             ResourceAddress,
-            ResourceAddress
+            ResourceAddress,
+            Global<TimeLock>
         ){
             let (address_reservation, component_address) =
             Runtime::allocate_component_address(DeXianProtocol::blueprint_id());
 
+            // the admin and manager badges can only queue and execute actions on the timelock,
+            // the privileged calls of the protocol components are accepted from the timelock only.
+            let timelock = TimeLock::instantiate(
+                admin_rule,
+                guardian_rule.clone(),
+                risk_manager_rule,
+                oracle_manager_rule,
+                treasurer_rule,
+                timelock_delay_epochs
+            );
+            let timelock_rule = rule!(require(global_caller(timelock.address())));
+
             let price_oracle = PriceOracle::instantiate(
-                OwnerRole::Fixed(admin_rule.clone()),
+                OwnerRole::Fixed(timelock_rule.clone()),
                 rule!(require(op_res_addr)),
-                admin_rule.clone(),
+                timelock_rule.clone(),
+                timelock_rule.clone(),
                 price_signer_pk,
                 price_validity_ms
             );
@@ -87,38 +150,51 @@ mod dexian_protocol{
                 validator_keeper,
                 unstake_epoch_num,
                 settle_gas,
-                admin_rule.clone(),
-                mgr_rule.clone()
+                timelock_rule.clone(),
+                mgr_rule.clone(),
+                timelock_rule.clone(),
+                timelock_rule.clone(),
+                keeper_rule.clone()
             );
             
             
             let caller_rule = rule!(require(global_caller(component_address)) || require(global_caller(staking_mgr.address())));
             let (cdp_mgr, cdp_res_addr) = CollateralDebtManager::instantiate(
-                admin_rule.clone(),
-                mgr_rule.clone(),
+                timelock_rule.clone(),
+                rule!(require(global_caller(component_address))),
                 caller_rule,
+                timelock_rule.clone(),
+                timelock_rule.clone(),
+                guardian_rule.clone(),
                 price_oracle
             );
             
             let dse_res_addr = staking_mgr.get_dse_token();
             let component = Self{
-                admin_rule: admin_rule.clone(),
+                admin_rule: timelock_rule.clone(),
                 op_rule: rule!(require(op_res_addr)),
+                paused: false,
+                swap_adapters: HashSet::new(),
+                keeper_reward: Decimal::ZERO,
+                keeper_vault: Vault::new(XRD),
                 dse_res_addr,
                 price_oracle,
                 staking_mgr,
                 cdp_mgr,
                 cdp_res_addr
             }.instantiate()
-            .prepare_to_globalize(OwnerRole::Fixed(admin_rule.clone()))
+            .prepare_to_globalize(OwnerRole::Fixed(timelock_rule.clone()))
             .with_address(address_reservation)
             .roles(roles! {
-                admin => admin_rule.clone();
+                admin => timelock_rule.clone();
                 operator => rule!(require(op_res_addr));
+                treasurer => timelock_rule.clone();
+                guardian => guardian_rule;
+                keeper => keeper_rule;
             })
             .globalize();
             
-            (component, price_oracle, staking_mgr, dse_res_addr, cdp_res_addr)
+            (component, price_oracle, staking_mgr, dse_res_addr, cdp_res_addr, timelock)
         }
 
         pub fn new_pool(&mut self,
@@ -152,7 +228,28 @@ mod dexian_protocol{
             )
         }
 
+        pub fn pause(&mut self){
+            self.paused = true;
+            Runtime::emit_event(SetPausedEvent{paused: true});
+        }
+
+        pub fn unpause(&mut self){
+            self.paused = false;
+            Runtime::emit_event(SetPausedEvent{paused: false});
+        }
+
+        pub fn set_swap_adapter(&mut self, swap_adapter: ComponentAddress, approved: bool){
+            if approved {
+                self.swap_adapters.insert(swap_adapter);
+            }
+            else{
+                assert!(self.swap_adapters.remove(&swap_adapter), "The swap adapter is not approved.");
+            }
+            Runtime::emit_event(SetSwapAdapterEvent{swap_adapter, approved});
+        }
+
         pub fn supply(&mut self, bucket: Bucket) -> Bucket{
+            self.assert_not_paused();
             let supply_token = bucket.resource_address();
             let supply_amount = bucket.amount();
             info!("{} supply {}", Runtime::bech32_encode_address(supply_token), supply_amount);
@@ -165,6 +262,7 @@ mod dexian_protocol{
         }
 
         pub fn withdraw(&mut self, bucket: Bucket) -> Bucket{
+            self.assert_not_paused();
             let dx_token = bucket.resource_address();
             let dx_amount = bucket.amount();
             info!("{} burn {}", Runtime::bech32_encode_address(dx_token), dx_amount);
@@ -176,8 +274,9 @@ mod dexian_protocol{
             withdraw_bucket
         }
 
+        /// The collateral could be either the dx token or its underlying token, which is supplied first.
         pub fn borrow_variable(&mut self,
-            dx_bucket: Bucket,
+            collateral_bucket: Bucket,
             borrow_token: ResourceAddress,
             borrow_amount: Decimal,
             price1: String,
@@ -189,6 +288,8 @@ mod dexian_protocol{
             timestamp2: Option<u64>,
             signature2: Option<String>
         ) -> (Bucket, Bucket){
+            self.assert_not_paused();
+            let dx_bucket = self.get_dx_bucket(collateral_bucket);
             let dx_token = dx_bucket.resource_address();
             let dx_amount = dx_bucket.amount();
             let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.extra_params(dx_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
@@ -200,8 +301,9 @@ mod dexian_protocol{
             (borrow_bucket, cdp_bucket)
         }
 
+        /// The collateral could be either the dx token or its underlying token, which is supplied first.
         pub fn borrow_stable(&mut self,
-            dx_bucket: Bucket,
+            collateral_bucket: Bucket,
             borrow_token: ResourceAddress,
             borrow_amount: Decimal,
             price1: String,
@@ -213,6 +315,8 @@ mod dexian_protocol{
             timestamp2: Option<u64>,
             signature2: Option<String>
         ) -> (Bucket, Bucket){
+            self.assert_not_paused();
+            let dx_bucket = self.get_dx_bucket(collateral_bucket);
             let dx_token = dx_bucket.resource_address();
             let dx_amount = dx_bucket.amount();
             let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.extra_params(dx_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
@@ -222,8 +326,102 @@ mod dexian_protocol{
             (borrow_bucket, cdp_bucket)
         }
 
+        /// Supplies the bucket(underlying token) and borrows against `collateral_amount` of it in one step.
+        /// Returns the dx token of the remaining supply, the borrowed bucket and the CDP.
+        pub fn supply_and_borrow(&mut self,
+            bucket: Bucket,
+            collateral_amount: Decimal,
+            borrow_token: ResourceAddress,
+            borrow_amount: Decimal,
+            is_stable: bool,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> (Bucket, Bucket, Bucket){
+            self.assert_not_paused();
+            let supply_amount = bucket.amount();
+            assert!(collateral_amount.is_positive() && collateral_amount <= supply_amount, "The collateral amount must be positive and not exceed the supply.");
+            let mut dx_bucket = self.supply(bucket);
+            let dx_token = dx_bucket.resource_address();
+            let collateral_dx_amount = if collateral_amount == supply_amount { dx_bucket.amount() } else {
+                floor_by_resource(dx_token, dx_bucket.amount().checked_mul(collateral_amount).unwrap().checked_div(supply_amount).unwrap())
+            };
+            let collateral_bucket = dx_bucket.take(collateral_dx_amount);
+            let (borrow_bucket, cdp_bucket) = if is_stable {
+                self.borrow_stable(collateral_bucket, borrow_token, borrow_amount, price1, quote1, timestamp1, signature1, price2, quote2, timestamp2, signature2)
+            } else {
+                self.borrow_variable(collateral_bucket, borrow_token, borrow_amount, price1, quote1, timestamp1, signature1, price2, quote2, timestamp2, signature2)
+            };
+            (dx_bucket, borrow_bucket, cdp_bucket)
+        }
+
+        /// Opens a variable rate CDP of `target_leverage` times exposure to the collateral(underlying token).
+        /// The extra collateral is bought with a flashloan of the borrow token through the swap adapter,
+        /// the flashloan is then repaid by the debt of the CDP. Returns the CDP and the remainder of the borrow token.
+        pub fn open_leveraged(&mut self,
+            collateral_bucket: Bucket,
+            borrow_token: ResourceAddress,
+            target_leverage: Decimal,
+            swap_adapter: ComponentAddress,
+            min_out: Decimal,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> (Bucket, Bucket){
+            self.assert_not_paused();
+            let collateral_token = collateral_bucket.resource_address();
+            let initial_amount = collateral_bucket.amount();
+            assert!(self.cdp_mgr.get_collateral_underlying_token(collateral_token) == collateral_token, "The collateral must be the underlying token.");
+            assert!(target_leverage > Decimal::ONE, "The target leverage must be greater than ONE.");
+            let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2.clone(), quote2, timestamp2, signature2.clone());
+            assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+
+            // value of the flashloan = initial collateral value * (leverage - 1)
+            let flashloan_amount = floor_by_resource(borrow_token, initial_amount
+                .checked_mul(target_leverage.checked_sub(Decimal::ONE).unwrap()).unwrap()
+                .checked_mul(collateral_underlying_price_in_xrd).unwrap()
+                .checked_div(borrow_price_in_xrd).unwrap()
+            );
+            let (loan_bucket, flashloan) = self.borrow_flashloan(borrow_token, flashloan_amount);
+            let flashloan_data = ResourceManager::from_address(flashloan.resource_address()).get_non_fungible_data::<FlashLoanData>(&flashloan.as_non_fungible().non_fungible_local_id());
+            let mut collateral_bucket = collateral_bucket;
+            collateral_bucket.put(self.swap(swap_adapter, loan_bucket, collateral_token, min_out));
+            let collateral_amount = collateral_bucket.amount();
+
+            // the debt covers the flashloan and its fee
+            let debt_amount = ceil_by_resource(borrow_token, flashloan_data.amount.checked_add(flashloan_data.fee).unwrap());
+            let (borrow_bucket, cdp_bucket) = self.borrow_variable(collateral_bucket, borrow_token, debt_amount, price1, quote1, timestamp1, signature1, price2, quote2, timestamp2, signature2);
+            let remain_bucket = self.repay_flashloan(borrow_bucket, flashloan);
+
+            let cdp_id = cdp_bucket.as_non_fungible().non_fungible_local_id();
+            let summary = self.cdp_mgr.get_cdp_summary(cdp_id.clone(), borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            assert!(summary.health_factor > Decimal::ONE, "The health factor of the leveraged position is too low.");
+            info!("open leveraged: {}*{} -> {}, debt:{}, health_factor:{}", initial_amount, target_leverage, collateral_amount, debt_amount, summary.health_factor);
+            Runtime::emit_event(OpenLeveragedEvent{
+                cdp_id,
+                collateral_token,
+                initial_amount,
+                collateral_amount,
+                borrow_token,
+                debt_amount,
+                target_leverage,
+                health_factor: summary.health_factor
+            });
+            (cdp_bucket, remain_bucket)
+        }
+
         pub fn extend_borrow(&mut self,
-            cdp: Bucket,
+            cdp_proof: NonFungibleProof,
             amount: Decimal,
             price1: String,
             quote1: ResourceAddress,
@@ -233,19 +431,20 @@ mod dexian_protocol{
             quote2: Option<ResourceAddress>,
             timestamp2: Option<u64>,
             signature2: Option<String>
-        ) -> (Bucket, Bucket){
-            let cdp_id: NonFungibleLocalId = cdp.as_non_fungible().non_fungible_local_id();
+        ) -> Bucket{
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
             let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
             let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
             assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
             info!("collateral {}|{}, {}|{} price:{}/{}", Runtime::bech32_encode_address(collateral_underlying_token), collateral_underlying_token.to_hex(), Runtime::bech32_encode_address(collateral_underlying_token),collateral_underlying_token.to_hex() , borrow_price_in_xrd, collateral_underlying_price_in_xrd);
-            let (borrow_bucket, cdp_bucket) = self.cdp_mgr.extend_borrow(cdp, amount, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            let borrow_bucket = self.cdp_mgr.extend_borrow(cdp_id.clone(), amount, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
             Runtime::emit_event(ExtendBorrowEvent{borrow_token, amount, cdp_id:cdp_id.clone()});
-            (borrow_bucket, cdp_bucket)
+            borrow_bucket
         }
 
         pub fn withdraw_collateral(&mut self,
-            cdp: Bucket,
+            cdp_proof: NonFungibleProof,
             amount: Decimal,
             price1: String,
             quote1: ResourceAddress,
@@ -255,38 +454,232 @@ mod dexian_protocol{
             quote2: Option<ResourceAddress>,
             timestamp2: Option<u64>,
             signature2: Option<String>
-        ) -> (Bucket, Bucket){
-            let cdp_id: NonFungibleLocalId = cdp.as_non_fungible().non_fungible_local_id();
+        ) -> Bucket{
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
             let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
             let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
             assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
-            let (underlying_bucket, cdp_bucket) = self.cdp_mgr.withdraw_collateral(cdp, amount, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            let underlying_bucket = self.cdp_mgr.withdraw_collateral(cdp_id.clone(), amount, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
             Runtime::emit_event(WithdrawCollateralEvent{underlying_token:collateral_underlying_token, amount:underlying_bucket.amount(), cdp_id:cdp_id.clone()});
-            (underlying_bucket, cdp_bucket)
+            underlying_bucket
         }
 
-        pub fn addition_collateral(&mut self, id: u64, bucket: Bucket){
-            let cdp_id = NonFungibleLocalId::integer(id);
+        pub fn addition_collateral(&mut self, cdp_proof: NonFungibleProof, bucket: Bucket){
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
             let amount = bucket.amount();
             let underlying_token = bucket.resource_address();
-            self.cdp_mgr.addition_collateral(id, bucket);
+            self.cdp_mgr.addition_collateral(cdp_id.clone(), bucket);
             
             Runtime::emit_event(AdditionCollateralEvent{cdp_id, underlying_token, amount});
         }
 
-        pub fn repay(&mut self, repay_bucket: Bucket, id: u64) -> Bucket{
-            let cdp_id: NonFungibleLocalId = NonFungibleLocalId::integer(id);
+        pub fn repay(&mut self, repay_bucket: Bucket, cdp_proof: NonFungibleProof) -> Bucket{
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
             let repay_token = repay_bucket.resource_address();
             let bucket_amount = repay_bucket.amount();
-            let (bucket, actual_payment) = self.cdp_mgr.repay(repay_bucket, id);
+            let (bucket, actual_payment) = self.cdp_mgr.repay(repay_bucket, cdp_id.clone());
             Runtime::emit_event(RepayEvent{cdp_id, repay_token, bucket_amount, actual_payment});
             bucket
         }
 
+        /// Repays the debt with `collateral_amount`(in underlying) of the collateral swapped through the adapter.
+        /// The debt is repaid in advance by a flashloan of `min_out`, so the collateral can be withdrawn without breaking
+        /// the health check, then the swap output repays the flashloan. Returns the leftover borrow token.
+        pub fn repay_with_collateral(&mut self,
+            cdp_proof: NonFungibleProof,
+            collateral_amount: Decimal,
+            swap_adapter: ComponentAddress,
+            min_out: Decimal,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> Bucket{
+            self.assert_not_paused();
+            assert!(collateral_amount.is_positive() && min_out.is_positive(), "The collateral amount and the minimum output must be positive.");
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
+            let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+            let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
+            assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+
+            let (mut loan_bucket, flashloan) = self.borrow_flashloan(borrow_token, min_out);
+            let flashloan_data = ResourceManager::from_address(flashloan.resource_address()).get_non_fungible_data::<FlashLoanData>(&flashloan.as_non_fungible().non_fungible_local_id());
+            // keep the flashloan fee aside, the rest repays the debt
+            let fee_bucket = loan_bucket.take(ceil_by_resource(borrow_token, flashloan_data.fee));
+            let bucket_amount = loan_bucket.amount();
+            let (mut repay_bucket, actual_payment) = self.cdp_mgr.repay(loan_bucket, cdp_id.clone());
+            Runtime::emit_event(RepayEvent{cdp_id: cdp_id.clone(), repay_token: borrow_token, bucket_amount, actual_payment});
+
+            let underlying_bucket = self.cdp_mgr.withdraw_collateral(cdp_id.clone(), collateral_amount, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            Runtime::emit_event(WithdrawCollateralEvent{underlying_token: collateral_underlying_token, amount: underlying_bucket.amount(), cdp_id});
+
+            repay_bucket.put(fee_bucket);
+            repay_bucket.put(self.swap(swap_adapter, underlying_bucket, borrow_token, min_out));
+            self.repay_flashloan(repay_bucket, flashloan)
+        }
+
+        pub fn close_cdp(&mut self, cdp: Bucket, repay_bucket: Bucket, withdraw_underlying: bool) -> (Bucket, Bucket){
+            let cdp_id: NonFungibleLocalId = cdp.as_non_fungible().non_fungible_local_id();
+            let repay_token = repay_bucket.resource_address();
+            let bucket_amount = repay_bucket.amount();
+            let (collateral_bucket, refund_bucket, actual_payment) = self.cdp_mgr.close_cdp(cdp, repay_bucket, withdraw_underlying);
+            Runtime::emit_event(CloseCDPEvent{
+                cdp_id,
+                repay_token,
+                bucket_amount,
+                actual_payment,
+                collateral_token: collateral_bucket.resource_address(),
+                collateral_amount: collateral_bucket.amount()
+            });
+            (collateral_bucket, refund_bucket)
+        }
+
+        pub fn split_cdp(&mut self,
+            cdp_proof: NonFungibleProof,
+            collateral_amount: Decimal,
+            debt_amount: Decimal,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> Bucket{
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
+            let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+            let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
+            assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+            let new_cdp = self.cdp_mgr.split_cdp(cdp_id.clone(), collateral_amount, debt_amount, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            Runtime::emit_event(SplitCDPEvent{
+                cdp_id,
+                new_cdp_id: new_cdp.as_non_fungible().non_fungible_local_id(),
+                collateral_amount,
+                debt_amount
+            });
+            new_cdp
+        }
+
+        pub fn merge_cdp(&mut self,
+            cdp_proof: NonFungibleProof,
+            other_cdp: Bucket,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ){
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
+            let merged_cdp_id: NonFungibleLocalId = other_cdp.as_non_fungible().non_fungible_local_id();
+            let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+            let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
+            assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+            self.cdp_mgr.merge_cdp(cdp_id.clone(), other_cdp, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            Runtime::emit_event(MergeCDPEvent{cdp_id, merged_cdp_id});
+        }
+
+        pub fn swap_collateral(&mut self,
+            cdp_proof: NonFungibleProof,
+            bucket: Bucket,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> Bucket{
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
+            let new_collateral_token = bucket.resource_address();
+            let new_collateral_amount = bucket.amount();
+            let (borrow_token, _) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+            let collateral_underlying_token = self.cdp_mgr.get_collateral_underlying_token(new_collateral_token);
+            let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
+            assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+            let old_dx_bucket = self.cdp_mgr.swap_collateral(cdp_id.clone(), bucket, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            Runtime::emit_event(SwapCollateralEvent{
+                cdp_id,
+                old_collateral_token: old_dx_bucket.resource_address(),
+                old_collateral_amount: old_dx_bucket.amount(),
+                new_collateral_token,
+                new_collateral_amount
+            });
+            old_dx_bucket
+        }
+
+        pub fn switch_rate_mode(&mut self, cdp_proof: NonFungibleProof){
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
+            let (is_stable, debt_amount, stable_rate) = self.cdp_mgr.switch_rate_mode(cdp_id.clone());
+            Runtime::emit_event(SwitchRateModeEvent{cdp_id, is_stable, debt_amount, stable_rate});
+        }
+
+        pub fn rebalance_stable_rate(&mut self, id: u64) -> Bucket{
+            let cdp_id = NonFungibleLocalId::integer(id);
+            let (previous_rate, stable_rate, debt_amount) = self.cdp_mgr.rebalance_stable_rate(cdp_id.clone());
+            // the reward is capped by what is left in the keeper vault
+            let reward_amount = if self.keeper_reward < self.keeper_vault.amount() { self.keeper_reward } else { self.keeper_vault.amount() };
+            let reward_bucket = self.keeper_vault.take(reward_amount);
+            Runtime::emit_event(RebalanceStableRateEvent{cdp_id, previous_rate, stable_rate, debt_amount, keeper_reward: reward_amount});
+            reward_bucket
+        }
+
+        pub fn set_keeper_reward(&mut self, keeper_reward: Decimal){
+            assert!(!keeper_reward.is_negative(), "The keeper reward can not be negative.");
+            let previous = self.keeper_reward;
+            self.keeper_reward = keeper_reward;
+            Runtime::emit_event(SetKeeperRewardEvent{previous, keeper_reward});
+        }
+
+        pub fn fund_keeper_reward(&mut self, bucket: Bucket){
+            self.keeper_vault.put(bucket);
+        }
+
+        pub fn withdraw_keeper_fund(&mut self, amount: Decimal) -> Bucket{
+            assert!(amount <= self.keeper_vault.amount(), "insufficient keeper fund balance");
+            self.keeper_vault.take(amount)
+        }
+
+        pub fn set_emode(&mut self,
+            cdp_proof: NonFungibleProof,
+            emode_category: u8,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ){
+            self.assert_not_paused();
+            let cdp_id: NonFungibleLocalId = self.get_cdp_id(cdp_proof);
+            let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+            let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
+            assert!(borrow_price_in_xrd.is_positive() && collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+            self.cdp_mgr.set_cdp_emode(cdp_id.clone(), emode_category, borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+            Runtime::emit_event(SetCDPEModeEvent{cdp_id, emode_category});
+        }
+
+        /// The seized collateral is returned as dx token when `receive_dx` is set or the collateral pool is short of liquidity.
         pub fn liquidation(&mut self,
             debt_bucket: Bucket,
             debt_to_cover: Decimal,
             id: u64,
+            receive_dx: bool,
             price1: String,
             quote1: ResourceAddress,
             timestamp1: u64,
@@ -296,28 +689,125 @@ mod dexian_protocol{
             timestamp2: Option<u64>,
             signature2: Option<String>
         ) -> (Bucket, Bucket){
-            let bucket_amount = debt_bucket.amount();
             let cdp_id = NonFungibleLocalId::integer(id);
             let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
             assert!(borrow_token == debt_bucket.resource_address(), "the borrow token does not matches CDP.");
             let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2, quote2, timestamp2, signature2);
             assert!(borrow_price_in_xrd.is_positive() || collateral_underlying_price_in_xrd.is_positive(), "Incorrect information on price signature.");
+            self.liquidate_cdp(debt_bucket, debt_to_cover, cdp_id, borrow_token, borrow_price_in_xrd, collateral_underlying_token, collateral_underlying_price_in_xrd, receive_dx)
+        }
 
-            let (underlying_bucket, refund_bucket) = self.cdp_mgr.liquidation(debt_bucket, debt_to_cover, cdp_id.clone(), borrow_price_in_xrd, collateral_underlying_token, collateral_underlying_price_in_xrd);
-            let underlying_amount = underlying_bucket.amount();
-            let actual_repayment = bucket_amount.checked_sub(refund_bucket.amount()).unwrap();
-            info!("underlying:{}, actual_repayment:{}", underlying_amount, actual_repayment);
-            Runtime::emit_event(LiquidationEvent{
-                cdp_id: cdp_id.clone(),
-                debt_token: borrow_token,
-                debt_price: borrow_price_in_xrd,
-                underlying_token: collateral_underlying_token,
-                underlying_price: collateral_underlying_price_in_xrd,
-                underlying_amount,
-                actual_repayment,
-                debt_to_cover
+        /// Liquidates several CDPs of the same debt token with one debt bucket. The prices are verified once per collateral,
+        /// CDPs which are closed, healthy or can not be priced by the signatures are skipped.
+        /// Returns the seized collateral aggregated by resource and the unused debt token.
+        pub fn liquidate_batch(&mut self,
+            debt_bucket: Bucket,
+            liquidations: Vec<(u64, Decimal)>,
+            receive_dx: bool,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> (Vec<Bucket>, Bucket){
+            let debt_token = debt_bucket.resource_address();
+            let cdp_res_mgr = ResourceManager::from_address(self.cdp_res_addr);
+            let mut prices: HashMap<ResourceAddress, (Decimal, Decimal)> = HashMap::new();
+            // keep the order of the seized collateral deterministic
+            let mut seized: IndexMap<ResourceAddress, Bucket> = IndexMap::new();
+            let mut refund_bucket = debt_bucket;
+
+            for (id, debt_to_cover) in liquidations {
+                // like a single liquidation, ZERO covers as much as possible, capped by the remaining debt bucket
+                let bucket_amount = refund_bucket.amount();
+                let debt_to_cover = if debt_to_cover.is_positive() && debt_to_cover < bucket_amount { debt_to_cover } else { bucket_amount };
+                if !debt_to_cover.is_positive() {
+                    info!("skip CDP#{}: the debt bucket is used up", id);
+                    continue;
+                }
+                let cdp_id = NonFungibleLocalId::integer(id);
+                if !cdp_res_mgr.non_fungible_exists(&cdp_id) {
+                    info!("skip CDP#{}: not found", id);
+                    continue;
+                }
+                // the CDPs of the staking earning are backed by XRD in unstaking, not by a dx token
+                let cdp_data = cdp_res_mgr.get_non_fungible_data::<CollateralDebtPosition>(&cdp_id);
+                if cdp_data.collateral_token == XRD {
+                    info!("skip CDP#{}: staking CDP", id);
+                    continue;
+                }
+                if cdp_data.borrow_token != debt_token {
+                    info!("skip CDP#{}: the borrow token does not match", id);
+                    continue;
+                }
+                let (borrow_token, collateral_underlying_token) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+                if !prices.contains_key(&collateral_underlying_token) {
+                    let price_pair = self.get_price_in_xrd(collateral_underlying_token, borrow_token, &price1, quote1, timestamp1, &signature1, price2.clone(), quote2, timestamp2, signature2.clone());
+                    prices.insert(collateral_underlying_token, price_pair);
+                }
+                let (borrow_price_in_xrd, collateral_underlying_price_in_xrd) = *prices.get(&collateral_underlying_token).unwrap();
+                if !borrow_price_in_xrd.is_positive() || !collateral_underlying_price_in_xrd.is_positive() {
+                    info!("skip CDP#{}: no price of the collateral", id);
+                    continue;
+                }
+                let summary = self.cdp_mgr.get_cdp_summary(cdp_id.clone(), borrow_price_in_xrd, collateral_underlying_price_in_xrd);
+                if !summary.debt_amount.is_positive() || summary.health_factor > Decimal::ONE {
+                    info!("skip CDP#{}: health_factor:{}", id, summary.health_factor);
+                    continue;
+                }
+
+                let (collateral_bucket, bucket) = self.liquidate_cdp(refund_bucket, debt_to_cover, cdp_id, borrow_token, borrow_price_in_xrd, collateral_underlying_token, collateral_underlying_price_in_xrd, receive_dx);
+                refund_bucket = bucket;
+                match seized.get_mut(&collateral_bucket.resource_address()) {
+                    Some(seized_bucket) => seized_bucket.put(collateral_bucket),
+                    None => { seized.insert(collateral_bucket.resource_address(), collateral_bucket); }
+                }
+            }
+            (seized.into_values().collect(), refund_bucket)
+        }
+
+        /// Liquidates the CDP with a flashloan of the debt token, the seized collateral is swapped back through the adapter
+        /// to repay the flashloan. Returns the profit in debt token, which must not be less than `min_profit`.
+        /// When the collateral pool is short of liquidity the seized collateral is dx token, which the adapter must accept.
+        pub fn flash_liquidate(&mut self,
+            id: u64,
+            debt_to_cover: Decimal,
+            swap_adapter: ComponentAddress,
+            min_profit: Decimal,
+            price1: String,
+            quote1: ResourceAddress,
+            timestamp1: u64,
+            signature1: String,
+            price2: Option<String>,
+            quote2: Option<ResourceAddress>,
+            timestamp2: Option<u64>,
+            signature2: Option<String>
+        ) -> Bucket{
+            let cdp_id = NonFungibleLocalId::integer(id);
+            let (borrow_token, _) = self.cdp_mgr.get_cdp_resource_address(cdp_id.clone());
+            // liquidations are not blocked by the pause, so the flashloan is taken from the cdp manager directly
+            let (loan_bucket, flashloan) = self.cdp_mgr.borrow_flashloan(borrow_token, debt_to_cover);
+            let flashloan_data = ResourceManager::from_address(flashloan.resource_address()).get_non_fungible_data::<FlashLoanData>(&flashloan.as_non_fungible().non_fungible_local_id());
+            let (underlying_bucket, mut repay_bucket) = self.liquidation(loan_bucket, debt_to_cover, id, false, price1, quote1, timestamp1, signature1, price2, quote2, timestamp2, signature2);
+
+            // the swap must cover the flashloan(with fee) not refunded by the liquidation, plus the minimum profit
+            let flashloan_total = ceil_by_resource(borrow_token, flashloan_data.amount.checked_add(flashloan_data.fee).unwrap());
+            let min_out = flashloan_total.checked_add(min_profit).unwrap().checked_sub(repay_bucket.amount()).unwrap();
+            let collateral_amount = underlying_bucket.amount();
+            repay_bucket.put(self.swap(swap_adapter, underlying_bucket, borrow_token, if min_out.is_positive() { min_out } else { Decimal::ZERO }));
+            let profit_bucket = self.repay_flashloan(repay_bucket, flashloan);
+            assert!(profit_bucket.amount() >= min_profit, "The profit of the liquidation is less than the minimum.");
+            Runtime::emit_event(FlashLiquidationEvent{
+                cdp_id,
+                swap_adapter,
+                collateral_amount,
+                profit_token: borrow_token,
+                profit: profit_bucket.amount()
             });
-            (underlying_bucket,refund_bucket)
+            profit_bucket
         }
 
         // pub fn migrate_cdp(&mut self, 
@@ -350,6 +840,7 @@ mod dexian_protocol{
         // }
 
         pub fn borrow_flashloan(&mut self, res_addr: ResourceAddress, amount: Decimal) -> (Bucket, Bucket){
+            self.assert_not_paused();
             self.cdp_mgr.borrow_flashloan(res_addr, amount)
         }
 
@@ -372,13 +863,97 @@ mod dexian_protocol{
         }
 
         pub fn join(&mut self, validator: ComponentAddress, xrd_bucket: Bucket) -> Bucket{
+            self.assert_not_paused();
             self.staking_mgr.join(validator, xrd_bucket)
         }
 
         pub fn redeem(&mut self, validator: ComponentAddress, bucket: Bucket, is_faster: bool) ->Bucket{
+            self.assert_not_paused();
             self.staking_mgr.redeem(self.cdp_mgr, validator, bucket, is_faster)
         }
 
+        /// Summary of a CDP evaluated at the given prices(in XRD), the prices are not verified.
+        pub fn get_cdp_summary(&self, id: u64, borrow_price_in_xrd: Decimal, collateral_underlying_price_in_xrd: Decimal) -> CDPSummary{
+            self.cdp_mgr.get_cdp_summary(NonFungibleLocalId::integer(id), borrow_price_in_xrd, collateral_underlying_price_in_xrd)
+        }
+
+        pub fn get_market(&self, res_addr: ResourceAddress) -> MarketInfo{
+            let underlying_token = self.cdp_mgr.get_collateral_underlying_token(res_addr);
+            self.cdp_mgr.get_market(underlying_token)
+        }
+
+        pub fn get_swap_adapters(&self) -> Vec<ComponentAddress>{
+            self.swap_adapters.iter().cloned().collect()
+        }
+
+        /// Output amount of swapping `input_amount` of the input token through an approved adapter.
+        pub fn quote_swap(&self, swap_adapter: ComponentAddress, input_token: ResourceAddress, input_amount: Decimal, output_token: ResourceAddress) -> Decimal{
+            assert!(self.swap_adapters.contains(&swap_adapter), "The swap adapter is not approved.");
+            adapter_quote(swap_adapter, input_token, input_amount, output_token)
+        }
+
+        fn assert_not_paused(&self){
+            assert!(!self.paused, "The protocol is paused.");
+        }
+
+        fn liquidate_cdp(&mut self,
+            debt_bucket: Bucket,
+            debt_to_cover: Decimal,
+            cdp_id: NonFungibleLocalId,
+            borrow_token: ResourceAddress,
+            borrow_price_in_xrd: Decimal,
+            collateral_underlying_token: ResourceAddress,
+            collateral_underlying_price_in_xrd: Decimal,
+            receive_dx: bool
+        ) -> (Bucket, Bucket){
+            let bucket_amount = debt_bucket.amount();
+            let (collateral_bucket, refund_bucket, liquidator_bonus, protocol_fee) = self.cdp_mgr.liquidation(debt_bucket, debt_to_cover, cdp_id.clone(), borrow_price_in_xrd, collateral_underlying_token, collateral_underlying_price_in_xrd, receive_dx);
+            let received_token = collateral_bucket.resource_address();
+            let underlying_amount = collateral_bucket.amount();
+            let actual_repayment = bucket_amount.checked_sub(refund_bucket.amount()).unwrap();
+            info!("underlying:{}, actual_repayment:{}", underlying_amount, actual_repayment);
+            Runtime::emit_event(LiquidationEvent{
+                cdp_id,
+                debt_token: borrow_token,
+                debt_price: borrow_price_in_xrd,
+                underlying_token: collateral_underlying_token,
+                underlying_price: collateral_underlying_price_in_xrd,
+                received_token,
+                underlying_amount,
+                actual_repayment,
+                debt_to_cover,
+                liquidator_bonus,
+                protocol_fee
+            });
+            (collateral_bucket, refund_bucket)
+        }
+
+        /// Supplies the bucket when it holds the underlying token of a pool, dx tokens are returned as is.
+        fn get_dx_bucket(&mut self, bucket: Bucket) -> Bucket{
+            let res_addr = bucket.resource_address();
+            if self.cdp_mgr.get_collateral_underlying_token(res_addr) == res_addr {
+                self.supply(bucket)
+            } else {
+                bucket
+            }
+        }
+
+        /// Swaps the bucket through an approved adapter, the output is checked against `min_out` regardless of the adapter.
+        fn swap(&self, swap_adapter: ComponentAddress, bucket: Bucket, output_token: ResourceAddress, min_out: Decimal) -> Bucket{
+            assert!(self.swap_adapters.contains(&swap_adapter), "The swap adapter is not approved.");
+            let input_amount = bucket.amount();
+            let output_bucket = adapter_swap(swap_adapter, bucket, output_token, min_out);
+            assert!(output_bucket.resource_address() == output_token, "The swap output token does not match.");
+            assert!(output_bucket.amount() >= min_out, "The swap output is less than the minimum.");
+            info!("swap {} -> {}*{}", input_amount, Runtime::bech32_encode_address(output_token), output_bucket.amount());
+            output_bucket
+        }
+
+        fn get_cdp_id(&self, cdp_proof: NonFungibleProof) -> NonFungibleLocalId{
+            let checked_proof = cdp_proof.check(self.cdp_res_addr);
+            checked_proof.non_fungible_local_id()
+        }
+
         fn extra_params(&self,
             dx_token: ResourceAddress,
             borrow_token: ResourceAddress,
@@ -430,6 +1005,11 @@ mod dexian_protocol{
 }
 
 
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SetPausedEvent {
+    pub paused: bool
+}
+
 #[derive(ScryptoSbor, ScryptoEvent)]
 pub struct SupplyEvent {
     pub supply_token: ResourceAddress,
@@ -485,6 +1065,62 @@ pub struct RepayEvent{
     pub actual_payment: Decimal
 }
 
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct CloseCDPEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub repay_token: ResourceAddress,
+    pub bucket_amount: Decimal,
+    pub actual_payment: Decimal,
+    pub collateral_token: ResourceAddress,
+    pub collateral_amount: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SplitCDPEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub new_cdp_id: NonFungibleLocalId,
+    pub collateral_amount: Decimal,
+    pub debt_amount: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct MergeCDPEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub merged_cdp_id: NonFungibleLocalId
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SwapCollateralEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub old_collateral_token: ResourceAddress,
+    pub old_collateral_amount: Decimal,
+    pub new_collateral_token: ResourceAddress,
+    pub new_collateral_amount: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SwitchRateModeEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub is_stable: bool,
+    pub debt_amount: Decimal,
+    pub stable_rate: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct RebalanceStableRateEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub previous_rate: Decimal,
+    pub stable_rate: Decimal,
+    pub debt_amount: Decimal,
+    pub keeper_reward: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SetCDPEModeEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub emode_category: u8
+}
+
 #[derive(ScryptoSbor, ScryptoEvent)]
 pub struct LiquidationEvent{
     pub cdp_id: NonFungibleLocalId,
@@ -494,7 +1130,14 @@ pub struct LiquidationEvent{
     pub actual_repayment: Decimal,
     pub underlying_token: ResourceAddress,
     pub underlying_price: Decimal,
-    pub underlying_amount: Decimal
+    /// the underlying token, or the dx token when the collateral is handed over as dx
+    pub received_token: ResourceAddress,
+    /// amount of the received token
+    pub underlying_amount: Decimal,
+    /// liquidation bonus(in underlying) received by the liquidator
+    pub liquidator_bonus: Decimal,
+    /// liquidation bonus(in underlying) kept by the protocol as insurance
+    pub protocol_fee: Decimal
 }
 
 #[derive(ScryptoSbor, ScryptoEvent)]
@@ -506,3 +1149,38 @@ pub struct FlashLoanEvent{
     pub nft_addr: ResourceAddress,
     pub nft_id: NonFungibleLocalId
 }
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SetSwapAdapterEvent{
+    pub swap_adapter: ComponentAddress,
+    pub approved: bool
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct SetKeeperRewardEvent{
+    pub previous: Decimal,
+    pub keeper_reward: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct OpenLeveragedEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub collateral_token: ResourceAddress,
+    pub initial_amount: Decimal,
+    /// initial collateral plus the swapped collateral
+    pub collateral_amount: Decimal,
+    pub borrow_token: ResourceAddress,
+    pub debt_amount: Decimal,
+    pub target_leverage: Decimal,
+    pub health_factor: Decimal
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct FlashLiquidationEvent{
+    pub cdp_id: NonFungibleLocalId,
+    pub swap_adapter: ComponentAddress,
+    /// seized collateral(in underlying) swapped through the adapter
+    pub collateral_amount: Decimal,
+    pub profit_token: ResourceAddress,
+    pub profit: Decimal
+}
@@ -0,0 +1,173 @@
+use scrypto::prelude::*;
+use crate::cdp::CollateralDebtPosition;
+use crate::pools::lending::lend_pool::LendResourcePool;
+use crate::utils::*;
+
+/// Current debt of the CDP in borrow token, including the accrued interest of either rate mode.
+pub fn get_current_debt(borrow_pool: &Global<LendResourcePool>, cdp_data: &CollateralDebtPosition) -> Decimal{
+    if cdp_data.is_stable {
+        let interest = borrow_pool.get_stable_interest(cdp_data.borrow_amount, cdp_data.last_update_epoch, cdp_data.stable_rate);
+        cdp_data.borrow_amount.checked_add(interest).unwrap()
+    }
+    else{
+        borrow_pool.get_variable_interest(cdp_data.normalized_borrow)
+    }
+}
+
+/// Sanity check of the collateral parameters: 0 < ltv < liquidation_threshold < 1 and the
+/// collateral seized with the bonus can not exceed the collateral backing the debt.
+pub fn assert_risk_params(ltv: Decimal, liquidation_threshold: Decimal, liquidation_bonus: Decimal){
+    assert!(ltv.is_positive() && ltv < liquidation_threshold, "The LTV must be positive and less than the liquidation threshold.");
+    assert!(liquidation_threshold < Decimal::ONE, "The liquidation threshold must be less than ONE.");
+    assert!(!liquidation_bonus.is_negative(), "The liquidation bonus can not be negative.");
+    assert!(
+        liquidation_threshold.checked_mul(Decimal::ONE.checked_add(liquidation_bonus).unwrap()).unwrap() <= Decimal::ONE,
+        "The liquidation threshold with the bonus must not exceed ONE."
+    );
+}
+
+/// Close factor(percent) of a liquidation: the position is liquidated in full when its health factor is below
+/// the critical health factor, or when the debt remaining after a partial liquidation would be dust.
+pub fn get_close_factor_percent(
+    close_factor_percent: Decimal,
+    critical_health_factor: Decimal,
+    dust_threshold: Decimal,
+    health_factor: Decimal,
+    debt_amount: Decimal
+) -> Decimal{
+    if health_factor < critical_health_factor {
+        return Decimal::ONE_HUNDRED;
+    }
+    let remain_debt = debt_amount.checked_sub(precent_mul(debt_amount, close_factor_percent)).unwrap();
+    if remain_debt < dust_threshold {
+        return Decimal::ONE_HUNDRED;
+    }
+    close_factor_percent
+}
+
+/// Splits the bonus included in the seized collateral between the protocol and the liquidator.
+/// Returns the protocol fee in collateral(dx token, floored to `collateral_divisibility`) and the liquidator bonus in underlying token.
+pub fn split_liquidation_bonus(
+    underlying_to_liquidate: Decimal,
+    liquidation_bonus: Decimal,
+    liquidation_protocol_fee: Decimal,
+    collateral_to_underlying_index: Decimal,
+    collateral_divisibility: u8
+) -> (Decimal, Decimal){
+    // underlying.amount * liquidation_bonus / (1+liquidation_bonus)
+    let bonus_amount = underlying_to_liquidate.checked_mul(liquidation_bonus).unwrap().checked_div(
+        Decimal::ONE.checked_add(liquidation_bonus).unwrap()
+    ).unwrap();
+    let protocol_fee_collateral = floor(
+        bonus_amount.checked_mul(liquidation_protocol_fee).unwrap().checked_div(collateral_to_underlying_index).unwrap(),
+        collateral_divisibility
+    );
+    let liquidator_bonus = bonus_amount.checked_sub(
+        protocol_fee_collateral.checked_mul(collateral_to_underlying_index).unwrap()
+    ).unwrap();
+    (protocol_fee_collateral, liquidator_bonus)
+}
+
+/// Linear ramp of a parameter from `start_value` at `start_epoch` to `target_value` at `end_epoch`.
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct ParameterRamp{
+    pub start_value: Decimal,
+    pub target_value: Decimal,
+    pub start_epoch: u64,
+    pub end_epoch: u64
+}
+
+impl ParameterRamp{
+
+    pub fn new(start_value: Decimal, target_value: Decimal, ramp_epochs: u64) -> ParameterRamp{
+        let start_epoch = Runtime::current_epoch().number();
+        ParameterRamp{
+            start_value,
+            target_value,
+            start_epoch,
+            end_epoch: start_epoch + ramp_epochs
+        }
+    }
+
+    pub fn is_in_progress(&self, epoch: u64) -> bool{
+        epoch < self.end_epoch
+    }
+
+    pub fn get_value(&self, epoch: u64) -> Decimal{
+        if epoch >= self.end_epoch {
+            return self.target_value;
+        }
+        if epoch <= self.start_epoch {
+            return self.start_value;
+        }
+        // start + (target - start) * (epoch - start_epoch) / (end_epoch - start_epoch)
+        let elapsed = Decimal::from(epoch - self.start_epoch);
+        let duration = Decimal::from(self.end_epoch - self.start_epoch);
+        self.start_value.checked_add(
+            self.target_value.checked_sub(self.start_value).unwrap()
+            .checked_mul(elapsed).unwrap()
+            .checked_div(duration).unwrap()
+        ).unwrap()
+    }
+}
+
+/// Risk figures of a position, the values are quoted in XRD.
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct PositionRisk{
+    /// current debt in borrow token
+    pub debt_amount: Decimal,
+    pub debt_value: Decimal,
+    /// collateral in underlying token
+    pub collateral_amount: Decimal,
+    pub collateral_value: Decimal,
+    /// the maximum debt(in borrow token) the collateral can carry under the LTV
+    pub borrow_capacity: Decimal,
+    /// collateral_value * liquidation_threshold / debt_value
+    pub health_factor: Decimal
+}
+
+impl PositionRisk{
+
+    pub fn new(
+        debt_amount: Decimal,
+        borrow_price_in_xrd: Decimal,
+        collateral_amount: Decimal,
+        collateral_price_in_xrd: Decimal,
+        ltv: Decimal,
+        liquidation_threshold: Decimal,
+        borrow_divisibility: u8
+    ) -> PositionRisk{
+        let debt_value = debt_amount.checked_mul(borrow_price_in_xrd).unwrap();
+        let collateral_value = collateral_amount.checked_mul(collateral_price_in_xrd).unwrap();
+        let borrow_capacity = if ltv.is_positive() && borrow_price_in_xrd.is_positive() && collateral_price_in_xrd.is_positive() {
+            floor(collateral_value.checked_mul(ltv).unwrap().checked_div(borrow_price_in_xrd).unwrap(), borrow_divisibility)
+        } else {
+            Decimal::ZERO
+        };
+        let health_factor = if debt_value.is_positive() {
+            collateral_value.checked_mul(liquidation_threshold).unwrap().checked_div(debt_value).unwrap()
+        } else {
+            Decimal::MAX
+        };
+        PositionRisk{
+            debt_amount,
+            debt_value,
+            collateral_amount,
+            collateral_value,
+            borrow_capacity,
+            health_factor
+        }
+    }
+
+    pub fn assert_borrowable(&self){
+        assert!(self.debt_amount <= self.borrow_capacity, "The amount borrowed exceeds the borrowable quantity of the collateral.");
+    }
+
+    pub fn assert_collateral_sufficient(&self){
+        assert!(self.debt_amount <= self.borrow_capacity, "Insufficient remaining collateral.");
+    }
+
+    pub fn is_liquidatable(&self) -> bool{
+        self.debt_amount.is_positive() && self.health_factor <= Decimal::ONE
+    }
+}
@@ -0,0 +1,86 @@
+use scrypto::prelude::*;
+use crate::utils::floor_by_resource;
+
+/// Interface of a DEX swap adapter. An adapter component must provide:
+///  - `swap(input_bucket: Bucket, output_resource: ResourceAddress, min_out: Decimal) -> Bucket`
+///  - `quote(input_resource: ResourceAddress, input_amount: Decimal, output_resource: ResourceAddress) -> Decimal`
+/// The adapters are called by method name, so any DEX could be wrapped by a component of its own blueprint.
+pub fn adapter_swap(swap_adapter: ComponentAddress, input_bucket: Bucket, output_resource: ResourceAddress, min_out: Decimal) -> Bucket{
+    let adapter: Global<AnyComponent> = Global::from(swap_adapter);
+    adapter.call_raw::<Bucket>("swap", scrypto_args!(input_bucket, output_resource, min_out))
+}
+
+pub fn adapter_quote(swap_adapter: ComponentAddress, input_resource: ResourceAddress, input_amount: Decimal, output_resource: ResourceAddress) -> Decimal{
+    let adapter: Global<AnyComponent> = Global::from(swap_adapter);
+    adapter.call_raw::<Decimal>("quote", scrypto_args!(input_resource, input_amount, output_resource))
+}
+
+/// Constant-product(x * y = k) adapter of a single pair, used by the tests and resim, never approved on a live network.
+#[blueprint]
+mod mock_swap{
+
+    enable_method_auth!{
+        methods {
+            swap => PUBLIC;
+            quote => PUBLIC;
+            get_reserves => PUBLIC;
+        }
+    }
+
+    struct MockSwapAdapter{
+        vault_a: Vault,
+        vault_b: Vault,
+        fee_ratio: Decimal
+    }
+
+    impl MockSwapAdapter{
+
+        pub fn instantiate(bucket_a: Bucket, bucket_b: Bucket, fee_ratio: Decimal) -> Global<MockSwapAdapter>{
+            assert!(bucket_a.resource_address() != bucket_b.resource_address(), "The pair must consist of two different tokens.");
+            assert!(bucket_a.amount().is_positive() && bucket_b.amount().is_positive(), "The initial liquidity must be positive.");
+            assert!(!fee_ratio.is_negative() && fee_ratio < Decimal::ONE, "The fee ratio must be in [0, 1).");
+            Self{
+                vault_a: Vault::with_bucket(bucket_a),
+                vault_b: Vault::with_bucket(bucket_b),
+                fee_ratio
+            }.instantiate()
+            .prepare_to_globalize(OwnerRole::None)
+            .globalize()
+        }
+
+        pub fn swap(&mut self, input_bucket: Bucket, output_resource: ResourceAddress, min_out: Decimal) -> Bucket{
+            let output_amount = self.quote(input_bucket.resource_address(), input_bucket.amount(), output_resource);
+            assert!(output_amount >= min_out, "The swap output is less than the minimum.");
+            if input_bucket.resource_address() == self.vault_a.resource_address() {
+                self.vault_a.put(input_bucket);
+                self.vault_b.take(output_amount)
+            }
+            else{
+                self.vault_b.put(input_bucket);
+                self.vault_a.take(output_amount)
+            }
+        }
+
+        pub fn quote(&self, input_resource: ResourceAddress, input_amount: Decimal, output_resource: ResourceAddress) -> Decimal{
+            let (input_reserve, output_reserve) = if input_resource == self.vault_a.resource_address() && output_resource == self.vault_b.resource_address() {
+                (self.vault_a.amount(), self.vault_b.amount())
+            }
+            else if input_resource == self.vault_b.resource_address() && output_resource == self.vault_a.resource_address() {
+                (self.vault_b.amount(), self.vault_a.amount())
+            }
+            else{
+                panic!("The pair is not supported.");
+            };
+            // output_reserve * input_with_fee / (input_reserve + input_with_fee)
+            let input_with_fee = input_amount.checked_mul(Decimal::ONE.checked_sub(self.fee_ratio).unwrap()).unwrap();
+            floor_by_resource(output_resource, output_reserve
+                .checked_mul(input_with_fee).unwrap()
+                .checked_div(input_reserve.checked_add(input_with_fee).unwrap()).unwrap()
+            )
+        }
+
+        pub fn get_reserves(&self) -> (ResourceAddress, Decimal, ResourceAddress, Decimal){
+            (self.vault_a.resource_address(), self.vault_a.amount(), self.vault_b.resource_address(), self.vault_b.amount())
+        }
+    }
+}
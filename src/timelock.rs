@@ -0,0 +1,211 @@
+use scrypto::prelude::*;
+
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct TimeLockAction{
+    pub target: ComponentAddress,
+    pub method: String,
+    // SBOR encoded arguments of the method
+    pub args: Vec<u8>,
+    // the action can be executed from this epoch
+    pub eta_epoch: u64,
+    // whether the method returns a bucket, which is handed back by `execute_withdrawal`
+    pub returns_bucket: bool
+}
+
+// the methods the risk, oracle and treasury managers can queue, anything else is queued by the admin.
+const RISK_METHODS: [&str; 19] = [
+    "set_ltv", "set_liquidation_threshold", "set_liquidation_bonus", "set_interest_model", "ramp_risk_params",
+    "cancel_ramp", "set_insurance_ratio", "set_flashloan_fee_ratio", "set_close_factor", "set_liquidation_protocol_fee",
+    "set_rebalance_threshold", "set_caps", "set_isolation", "set_borrowable_in_isolation", "set_emode_category",
+    "set_asset_emode_category", "set_pair_config", "remove_pair_config", "set_unstake_epoch_num"
+];
+const ORACLE_METHODS: [&str; 2] = ["set_verify_public_key", "set_validity_period"];
+const TREASURY_METHODS: [&str; 3] = ["withdraw_insurance", "withdraw_relayer_fee", "withdraw_keeper_fund"];
+
+#[blueprint]
+#[events(QueueActionEvent, CancelActionEvent, ExecuteActionEvent)]
+mod timelock{
+
+    enable_method_auth!{
+        roles{
+            admin => updatable_by: [];
+            guardian => updatable_by: [];
+            risk_manager => updatable_by: [admin];
+            oracle_manager => updatable_by: [admin];
+            treasurer => updatable_by: [admin];
+        },
+        methods {
+            //admin
+            queue => restrict_to: [admin, OWNER];
+            execute => restrict_to: [admin, risk_manager, oracle_manager, treasurer, OWNER];
+            execute_withdrawal => restrict_to: [admin, treasurer, OWNER];
+
+            //managers
+            queue_risk_action => restrict_to: [risk_manager, OWNER];
+            queue_oracle_action => restrict_to: [oracle_manager, OWNER];
+            queue_treasury_action => restrict_to: [treasurer, OWNER];
+
+            //guardian
+            cancel => restrict_to: [guardian, admin, OWNER];
+
+            //public
+            get_action => PUBLIC;
+            get_min_delay => PUBLIC;
+        }
+    }
+
+    struct TimeLock{
+        actions: KeyValueStore<u64, TimeLockAction>,
+        action_counter: u64,
+        min_delay_epochs: u64
+    }
+
+    impl TimeLock{
+
+        /// Governance queue of privileged calls, an action can only be executed `min_delay_epochs` after it was queued.
+        pub fn instantiate(
+            admin_rule: AccessRule,
+            guardian_rule: AccessRule,
+            risk_manager_rule: AccessRule,
+            oracle_manager_rule: AccessRule,
+            treasurer_rule: AccessRule,
+            min_delay_epochs: u64
+        ) -> Global<TimeLock>{
+            Self{
+                actions: KeyValueStore::new(),
+                action_counter: 0u64,
+                min_delay_epochs
+            }.instantiate()
+            .prepare_to_globalize(OwnerRole::Fixed(admin_rule.clone()))
+            .roles(roles!(
+                admin => admin_rule;
+                guardian => guardian_rule;
+                risk_manager => risk_manager_rule;
+                oracle_manager => oracle_manager_rule;
+                treasurer => treasurer_rule;
+            ))
+            .globalize()
+        }
+
+        pub fn queue(&mut self,
+            target: ComponentAddress,
+            method: String,
+            args: ScryptoValue,
+            eta_epoch: u64,
+            returns_bucket: bool
+        ) -> u64{
+            self.queue_action(target, method, args, eta_epoch, returns_bucket)
+        }
+
+        pub fn queue_risk_action(&mut self, target: ComponentAddress, method: String, args: ScryptoValue, eta_epoch: u64) -> u64{
+            assert!(RISK_METHODS.contains(&method.as_str()), "The method is not a risk management method.");
+            self.queue_action(target, method, args, eta_epoch, false)
+        }
+
+        pub fn queue_oracle_action(&mut self, target: ComponentAddress, method: String, args: ScryptoValue, eta_epoch: u64) -> u64{
+            assert!(ORACLE_METHODS.contains(&method.as_str()), "The method is not an oracle management method.");
+            self.queue_action(target, method, args, eta_epoch, false)
+        }
+
+        pub fn queue_treasury_action(&mut self, target: ComponentAddress, method: String, args: ScryptoValue, eta_epoch: u64) -> u64{
+            assert!(TREASURY_METHODS.contains(&method.as_str()), "The method is not a treasury method.");
+            self.queue_action(target, method, args, eta_epoch, true)
+        }
+
+        pub fn cancel(&mut self, id: u64){
+            assert!(self.actions.get(&id).is_some(), "The action does not exist.");
+            self.actions.remove(&id);
+            Runtime::emit_event(CancelActionEvent{id});
+        }
+
+        pub fn execute(&mut self, id: u64){
+            let action = self.take_ready_action(id);
+            assert!(!action.returns_bucket, "The action returns a bucket, it must be executed as a withdrawal.");
+            let target: Global<AnyComponent> = Global::from(action.target);
+            target.call_raw::<ScryptoValue>(&action.method, action.args);
+        }
+
+        /// Executes an action returning a bucket, which is handed back to the admin or the treasurer.
+        pub fn execute_withdrawal(&mut self, id: u64) -> Bucket{
+            let action = self.take_ready_action(id);
+            assert!(action.returns_bucket, "The action does not return a bucket.");
+            let target: Global<AnyComponent> = Global::from(action.target);
+            target.call_raw::<Bucket>(&action.method, action.args)
+        }
+
+        fn take_ready_action(&mut self, id: u64) -> TimeLockAction{
+            let action = self.actions.remove(&id);
+            assert!(action.is_some(), "The action does not exist.");
+            let action = action.unwrap();
+            assert!(Runtime::current_epoch().number() >= action.eta_epoch, "The action is still locked.");
+            info!("execute action {}: {}.{}", id, Runtime::bech32_encode_address(action.target), action.method);
+
+            Runtime::emit_event(ExecuteActionEvent{
+                id,
+                target: action.target,
+                method: action.method.clone()
+            });
+            action
+        }
+
+        fn queue_action(&mut self,
+            target: ComponentAddress,
+            method: String,
+            args: ScryptoValue,
+            eta_epoch: u64,
+            returns_bucket: bool
+        ) -> u64{
+            // the arguments are given as a tuple of the method parameters and kept encoded for `call_raw`.
+            let args = scrypto_encode(&args).unwrap();
+            let current_epoch = Runtime::current_epoch().number();
+            assert!(eta_epoch >= current_epoch + self.min_delay_epochs, "The execution epoch does not satisfy the minimum delay.");
+            self.action_counter += 1;
+            let id = self.action_counter;
+            Runtime::emit_event(QueueActionEvent{
+                id,
+                target,
+                method: method.clone(),
+                args: args.clone(),
+                eta_epoch
+            });
+            self.actions.insert(id, TimeLockAction{
+                target,
+                method,
+                args,
+                eta_epoch,
+                returns_bucket
+            });
+            id
+        }
+
+        pub fn get_action(&self, id: u64) -> Option<TimeLockAction>{
+            self.actions.get(&id).map(|action| action.clone())
+        }
+
+        pub fn get_min_delay(&self) -> u64{
+            self.min_delay_epochs
+        }
+    }
+}
+
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct QueueActionEvent{
+    pub id: u64,
+    pub target: ComponentAddress,
+    pub method: String,
+    pub args: Vec<u8>,
+    pub eta_epoch: u64
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct CancelActionEvent{
+    pub id: u64
+}
+
+#[derive(ScryptoSbor, ScryptoEvent)]
+pub struct ExecuteActionEvent{
+    pub id: u64,
+    pub target: ComponentAddress,
+    pub method: String
+}
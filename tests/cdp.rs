@@ -0,0 +1,296 @@
+mod common;
+
+use scrypto::prelude::*;
+use transaction::builder::ManifestBuilder;
+use common::*;
+
+#[test]
+fn test_close_cdp(){
+    let mut env = Env::new();
+    let (usdt, cdp_res) = (env.usdt, env.cdp_res);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(dec!("30"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+    let xrd_before = env.balance(&borrower, XRD);
+
+    env.close_cdp(&borrower, 1u64, usdt, dec!("30"), true).expect_commit_success();
+
+    // 25 USDT repaid out of the 30, the whole collateral is withdrawn and the CDP is burnt
+    assert_approx(env.balance(&borrower, usdt), dec!("30"));
+    assert_approx(env.balance(&borrower, XRD) - xrd_before, dec!("1000"));
+    assert_eq!(env.balance(&borrower, cdp_res), Decimal::ZERO);
+    assert!(!env.cdp_summary_receipt(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE).is_commit_success());
+    assert_approx(env.market(usdt).total_variable_borrow, Decimal::ZERO);
+}
+
+#[test]
+fn test_close_cdp_keeps_dx_collateral(){
+    let mut env = Env::new();
+    let (usdt, dx_xrd) = (env.usdt, env.dx_xrd);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(dec!("30"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), true).expect_commit_success();
+
+    env.close_cdp(&borrower, 1u64, usdt, dec!("30"), false).expect_commit_success();
+    assert_approx(env.balance(&borrower, dx_xrd), dec!("1000"));
+    assert_approx(env.market(usdt).total_stable_borrow, Decimal::ZERO);
+}
+
+#[test]
+fn test_close_cdp_with_insufficient_repayment(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    let receipt = env.close_cdp(&borrower, 1u64, usdt, dec!("20"), true);
+    expect_failure(receipt, "The repay bucket does not cover the whole debt of the CDP.");
+}
+
+#[test]
+fn test_manage_cdp_with_proof(){
+    let mut env = Env::new();
+    let (usdt, cdp_res) = (env.usdt, env.cdp_res);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.extend_borrow(&borrower, 1u64, dec!("4"), &prices).expect_commit_success();
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.withdraw_collateral(&borrower, 1u64, dec!("10"), &prices).expect_commit_success();
+    env.repay(&borrower, 1u64, usdt, dec!("10")).expect_commit_success();
+    env.addition_collateral(&borrower, 1u64, XRD, dec!("100")).expect_commit_success();
+
+    // the CDP never leaves the account of the borrower
+    assert_eq!(env.balance(&borrower, cdp_res), Decimal::ONE);
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("19"));
+    assert_approx(summary.collateral_amount, dec!("1090"));
+}
+
+#[test]
+fn test_manage_cdp_rejected(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    // the lender holds no proof of the CDP, the borrower can not withdraw below the LTV
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.extend_borrow(&lender, 1u64, dec!("4"), &prices).expect_commit_failure();
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.withdraw_collateral(&borrower, 1u64, dec!("500"), &prices).expect_commit_failure();
+}
+
+#[test]
+fn test_split_and_merge_cdp(){
+    let mut env = Env::new();
+    let (usdt, cdp_res) = (env.usdt, env.cdp_res);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.split_cdp(&borrower, 1u64, dec!("400"), dec!("10"), &prices).expect_commit_success();
+    assert_eq!(env.balance(&borrower, cdp_res), dec!("2"));
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("15"));
+    assert_approx(summary.collateral_amount, dec!("600"));
+    let summary = env.cdp_summary(2u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("10"));
+    assert_approx(summary.collateral_amount, dec!("400"));
+
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.merge_cdp(&borrower, 1u64, 2u64, &prices).expect_commit_success();
+    assert_eq!(env.balance(&borrower, cdp_res), Decimal::ONE);
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("25"));
+    assert_approx(summary.collateral_amount, dec!("1000"));
+    assert!(!env.cdp_summary_receipt(2u64, price_in_xrd(XRD_PRICE), Decimal::ONE).is_commit_success());
+}
+
+#[test]
+fn test_split_cdp_keeps_accrued_interest(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    // a high utilization of the USDT pool, so that the interest is noticeable
+    env.supply(&lender, usdt, dec!("30")).expect_commit_success();
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), true).expect_commit_success();
+    env.advance_epochs(10000u64);
+
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    let accrued_interest = summary.accrued_interest;
+    assert!(accrued_interest.is_positive());
+    let split_debt = summary.debt_amount / dec!("2");
+
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.split_cdp(&borrower, 1u64, dec!("500"), split_debt, &prices).expect_commit_success();
+
+    // the interest accrued before the split is shared in proportion to the debt
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    let new_summary = env.cdp_summary(2u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.debt_amount, split_debt);
+    assert_approx(new_summary.debt_amount, split_debt);
+    assert_approx(summary.accrued_interest, accrued_interest / dec!("2"));
+    assert_approx(new_summary.accrued_interest, accrued_interest / dec!("2"));
+}
+
+#[test]
+fn test_swap_collateral(){
+    let mut env = Env::new();
+    let (usdt, usdc, dx_xrd) = (env.usdt, env.usdc, env.dx_xrd);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, dec!("100"));
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    // 20 USDC can only back 17 USDT
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    env.swap_collateral(&borrower, 1u64, usdc, dec!("20"), &prices).expect_commit_failure();
+
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    env.swap_collateral(&borrower, 1u64, usdc, dec!("100"), &prices).expect_commit_success();
+    assert_approx(env.balance(&borrower, dx_xrd), dec!("1000"));
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), price_in_xrd(XRD_PRICE));
+    assert_eq!(summary.collateral_token, usdc);
+    assert_approx(summary.collateral_amount, dec!("100"));
+    assert_approx(summary.debt_amount, dec!("25"));
+}
+
+#[test]
+fn test_switch_rate_mode(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    // a high utilization of the USDT pool, so that the interest is noticeable
+    env.supply(&lender, usdt, dec!("30")).expect_commit_success();
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    // variable to stable, the pool moves the debt from the variable shares to the stable loans
+    env.switch_rate_mode(&borrower, 1u64).expect_commit_success();
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert!(summary.is_stable);
+    assert!(summary.stable_rate.is_positive());
+    assert_approx(summary.debt_amount, dec!("25"));
+    let market = env.market(usdt);
+    assert_approx(market.total_variable_borrow, Decimal::ZERO);
+    assert_approx(market.total_stable_borrow, dec!("25"));
+
+    // stable to variable, the accrued stable interest is capitalized
+    env.advance_epochs(10000u64);
+    let debt_amount = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE).debt_amount;
+    assert!(debt_amount > dec!("25"));
+    env.switch_rate_mode(&borrower, 1u64).expect_commit_success();
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert!(!summary.is_stable);
+    assert_eq!(summary.stable_rate, Decimal::ZERO);
+    assert_approx(summary.debt_amount, debt_amount);
+    let market = env.market(usdt);
+    assert_approx(market.total_variable_borrow, debt_amount);
+    assert_approx(market.total_stable_borrow, Decimal::ZERO);
+}
+
+#[test]
+fn test_rebalance_stable_rate(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    // any supply rate below 100% allows the rebalancing
+    env.govern(cdp_mgr, "set_rebalance_threshold", (usdt, Decimal::ONE, Decimal::ZERO)).expect_commit_success();
+    let lender = env.new_account(dec!("100"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdt, dec!("100")).expect_commit_success();
+    env.open_xrd_cdp(&lender, &borrower, dec!("10"), true).expect_commit_success();
+    let stable_rate = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE).stable_rate;
+
+    // the utilization rises from 10% to 30%
+    env.open_xrd_cdp(&lender, &other_borrower, dec!("20"), false).expect_commit_success();
+    let current_stable_rate = env.market(usdt).stable_borrow_rate;
+    assert!(current_stable_rate > stable_rate);
+
+    // only the keeper rebalances
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(env.protocol, "rebalance_stable_rate", manifest_args!(1u64))
+        .build();
+    env.execute(manifest, &[&borrower]).expect_commit_failure();
+
+    env.rebalance_stable_rate(1u64).expect_commit_success();
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert!(summary.stable_rate > stable_rate);
+    assert_approx(summary.debt_amount, dec!("10"));
+}
+
+#[test]
+fn test_rebalance_rejects_staking_cdp(){
+    let mut env = Env::new();
+    let lender = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let staker = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let validator = env.new_validator(&staker);
+    env.supply(&lender, XRD, dec!("5000")).expect_commit_success();
+
+    // the faster redemption borrows the unstaked XRD from the XRD pool against the claim of the staking earning
+    let dse = env.dse;
+    env.join(&staker, validator, dec!("1000")).expect_commit_success();
+    let dse_amount = env.balance(&staker, dse);
+    let xrd_before = env.balance(&staker, XRD);
+    env.redeem(&staker, validator, dse, dse_amount, true).expect_commit_success();
+    assert!(env.balance(&staker, XRD) > xrd_before);
+    assert!(env.market(XRD).total_stable_borrow.is_positive());
+
+    expect_failure(env.rebalance_stable_rate(1u64), "The staking CDP can not be rebalanced.");
+}
+
+#[test]
+fn test_stable_debt_limits_collateral_withdrawal(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("30"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdt, dec!("30")).expect_commit_success();
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), true).expect_commit_success();
+
+    // 800 XRD back 24 USDT only
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.withdraw_collateral(&borrower, 1u64, dec!("200"), &prices).expect_commit_failure();
+
+    // 834 XRD would back the 25 USDT of principal, but not the accrued stable interest
+    env.advance_epochs(10000u64);
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert!(summary.debt_amount > dec!("25.1"));
+    assert!(summary.max_withdrawable < dec!("166"));
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.withdraw_collateral(&borrower, 1u64, dec!("166"), &prices).expect_commit_failure();
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.withdraw_collateral(&borrower, 1u64, summary.max_withdrawable, &prices).expect_commit_success();
+}
+
+#[test]
+fn test_supply_and_borrow(){
+    let mut env = Env::new();
+    let (usdt, dx_xrd) = (env.usdt, env.dx_xrd);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdt, dec!("1000")).expect_commit_success();
+
+    // 600 of the 1000 XRD back the CDP, the dx receipt of the rest is returned
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.supply_and_borrow(&borrower, XRD, dec!("1000"), dec!("600"), usdt, dec!("15"), false, &prices).expect_commit_success();
+    assert_approx(env.balance(&borrower, dx_xrd), dec!("400"));
+    assert_eq!(env.balance(&borrower, usdt), dec!("15"));
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.collateral_amount, dec!("600"));
+    assert_approx(summary.debt_amount, dec!("15"));
+
+    // the dx token is accepted as collateral as well as the underlying
+    env.supply(&other_borrower, XRD, dec!("1000")).expect_commit_success();
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.borrow(&other_borrower, dx_xrd, dec!("1000"), usdt, dec!("25"), false, &prices).expect_commit_success();
+    assert_eq!(env.balance(&other_borrower, usdt), dec!("25"));
+}
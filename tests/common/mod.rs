@@ -0,0 +1,675 @@
+#![allow(dead_code)]
+
+use std::str::FromStr;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::{ManifestBuilder, ResolvableArguments};
+use transaction::model::TransactionManifestV1;
+use radix_engine::transaction::TransactionReceipt;
+use ed25519_dalek::{Signer, SigningKey};
+use dexian_lending_protocol::cdp::{CDPSummary, MarketInfo};
+
+pub const TIMELOCK_DELAY: u64 = 10;
+// XRD/USDT and XRD/USDC signed by default, 1 USD = 20 XRD
+pub const XRD_PRICE: &str = "0.05";
+
+// mirrors `interest::InterestModel`, only used to encode the arguments of `new_pool`
+#[derive(ManifestSbor)]
+pub enum InterestModel{
+    Default,
+    StableCoin
+}
+
+#[derive(Clone)]
+pub struct Account{
+    pub public_key: Secp256k1PublicKey,
+    pub address: ComponentAddress
+}
+
+pub struct Badges{
+    pub admin: ResourceAddress,
+    pub op: ResourceAddress,
+    pub risk_manager: ResourceAddress,
+    pub oracle_manager: ResourceAddress,
+    pub treasurer: ResourceAddress,
+    pub guardian: ResourceAddress,
+    pub keeper: ResourceAddress
+}
+
+/// The signed price arguments shared by the borrow, liquidation and CDP management methods.
+#[derive(Clone)]
+pub struct Prices{
+    pub price1: String,
+    pub quote1: ResourceAddress,
+    pub timestamp1: u64,
+    pub signature1: String,
+    pub price2: Option<String>,
+    pub quote2: Option<ResourceAddress>,
+    pub timestamp2: Option<u64>,
+    pub signature2: Option<String>
+}
+
+// appends the eight price arguments to the leading arguments of a protocol method
+macro_rules! price_args {
+    ($prices:expr; $($arg:expr),+) => {
+        manifest_args!(
+            $($arg),+,
+            $prices.price1.clone(), $prices.quote1, $prices.timestamp1, $prices.signature1.clone(),
+            $prices.price2.clone(), $prices.quote2, $prices.timestamp2, $prices.signature2.clone()
+        )
+    };
+}
+
+/// USD price of XRD, in XRD per USD as the oracle returns it.
+pub fn price_in_xrd(xrd_price_in_quote: &str) -> Decimal{
+    Decimal::ONE.checked_div(Decimal::from_str(xrd_price_in_quote).unwrap()).unwrap()
+}
+
+pub fn assert_approx(actual: Decimal, expected: Decimal){
+    let diff = if actual > expected { actual.checked_sub(expected).unwrap() } else { expected.checked_sub(actual).unwrap() };
+    assert!(diff < dec!("0.000001"), "expected {}, got {}", expected, actual);
+}
+
+pub fn expect_failure(receipt: TransactionReceipt, message: &str){
+    receipt.expect_specific_failure(|e| format!("{:?}", e).contains(message));
+}
+
+/// The protocol deployed on a simulated ledger with the XRD, USDT and USDC markets.
+pub struct Env{
+    pub runner: DefaultTestRunner,
+    pub package: PackageAddress,
+    pub admin: Account,
+    pub badges: Badges,
+    pub usdt: ResourceAddress,
+    pub usdc: ResourceAddress,
+    pub dx_xrd: ResourceAddress,
+    pub dx_usdt: ResourceAddress,
+    pub dx_usdc: ResourceAddress,
+    pub interest_model: ComponentAddress,
+    pub protocol: ComponentAddress,
+    pub oracle: ComponentAddress,
+    pub staking: ComponentAddress,
+    pub cdp_mgr: ComponentAddress,
+    pub timelock: ComponentAddress,
+    pub dse: ResourceAddress,
+    pub cdp_res: ResourceAddress,
+    signing_key: SigningKey,
+    timestamp: u64
+}
+
+impl Env{
+
+    pub fn new() -> Self{
+        let mut runner = TestRunnerBuilder::new().build();
+        let (public_key, _, address) = runner.new_allocated_account();
+        let admin = Account{public_key, address};
+        let package = runner.compile_and_publish(this_package!());
+        let badges = Badges{
+            admin: runner.create_fungible_resource(Decimal::ONE, 0u8, address),
+            op: runner.create_fungible_resource(Decimal::ONE, 0u8, address),
+            risk_manager: runner.create_fungible_resource(Decimal::ONE, 0u8, address),
+            oracle_manager: runner.create_fungible_resource(Decimal::ONE, 0u8, address),
+            treasurer: runner.create_fungible_resource(Decimal::ONE, 0u8, address),
+            guardian: runner.create_fungible_resource(Decimal::ONE, 0u8, address),
+            keeper: runner.create_fungible_resource(Decimal::ONE, 0u8, address)
+        };
+        let usdt = runner.create_fungible_resource(dec!("1000000"), 18u8, address);
+        let usdc = runner.create_fungible_resource(dec!("1000000"), 18u8, address);
+        let signers = vec![NonFungibleGlobalId::from_public_key(&public_key)];
+
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_function(package, "ValidatorKeeper", "instantiate", manifest_args!())
+            .call_method(address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        let receipt = runner.execute_manifest_ignoring_fee(manifest, signers.clone());
+        let validator_keeper = receipt.expect_commit_success().new_component_addresses()[0];
+
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_function(package, "DefInterestModel", "instantiate", manifest_args!(
+                validator_keeper, dec!("0.2"), dec!("0.5"), dec!("0.55"), dec!("0.45")
+            ))
+            .build();
+        let receipt = runner.execute_manifest_ignoring_fee(manifest, signers.clone());
+        let interest_model = receipt.expect_commit_success().new_component_addresses()[0];
+
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_function(package, "DeXianProtocol", "instantiate", manifest_args!(
+                validator_keeper,
+                rule!(require(badges.admin)),
+                badges.op,
+                hex::encode(signing_key.verifying_key().to_bytes()),
+                30000u64,
+                2016u64,
+                dec!("3"),
+                rule!(require(badges.risk_manager)),
+                rule!(require(badges.oracle_manager)),
+                rule!(require(badges.treasurer)),
+                rule!(require(badges.guardian)),
+                rule!(require(badges.keeper)),
+                TIMELOCK_DELAY
+            ))
+            .call_method(address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        let receipt = runner.execute_manifest_ignoring_fee(manifest, signers.clone());
+        let commit = receipt.expect_commit_success();
+        let (protocol, oracle, staking, dse, cdp_res, timelock) = commit.output::<(
+            ComponentAddress, ComponentAddress, ComponentAddress, ResourceAddress, ResourceAddress, ComponentAddress
+        )>(1);
+
+        // the address of the cdp manager is not returned, it is the only new component answering its queries
+        let candidates: Vec<ComponentAddress> = commit.new_component_addresses().iter()
+            .filter(|component| ![protocol, oracle, staking, timelock].contains(component))
+            .cloned()
+            .collect();
+        let cdp_mgr = candidates.into_iter().find(|component| {
+            let manifest = ManifestBuilder::new()
+                .lock_fee_from_faucet()
+                .call_method(*component, "get_emode_category", manifest_args!(1u8))
+                .build();
+            runner.execute_manifest_ignoring_fee(manifest, Vec::<NonFungibleGlobalId>::new()).is_commit_success()
+        }).expect("The cdp manager is not found.");
+
+        let mut env = Self{
+            runner,
+            package,
+            admin,
+            badges,
+            usdt,
+            usdc,
+            dx_xrd: XRD,
+            dx_usdt: usdt,
+            dx_usdc: usdc,
+            interest_model,
+            protocol,
+            oracle,
+            staking,
+            cdp_mgr,
+            timelock,
+            dse,
+            cdp_res,
+            signing_key,
+            timestamp: 1700000000u64
+        };
+        env.init_markets();
+        env
+    }
+
+    fn init_markets(&mut self){
+        let pools = [
+            (XRD, InterestModel::Default, dec!("0.6"), dec!("0.7"), dec!("0.07"), dec!("0.25")),
+            (self.usdt, InterestModel::StableCoin, dec!("0.85"), dec!("0.87"), dec!("0.02"), dec!("0.1")),
+            (self.usdc, InterestModel::StableCoin, dec!("0.85"), dec!("0.87"), dec!("0.02"), dec!("0.1"))
+        ];
+        let mut ids = Vec::new();
+        for (token, interest_model, ltv, liquidation_threshold, liquidation_bonus, insurance_ratio) in pools {
+            let eta_epoch = self.current_epoch() + TIMELOCK_DELAY;
+            let receipt = self.queue(self.protocol, "new_pool", (
+                18u8, token, interest_model, self.interest_model, ltv, liquidation_threshold, liquidation_bonus, insurance_ratio, dec!("0.001")
+            ), eta_epoch, false);
+            ids.push(receipt.expect_commit_success().output::<u64>(2));
+        }
+        self.advance_epochs(TIMELOCK_DELAY);
+        for id in ids {
+            let receipt = self.execute_action(self.badges.admin, id);
+            receipt.expect_commit_success();
+        }
+        self.dx_xrd = self.market(XRD).dx_token;
+        self.dx_usdt = self.market(self.usdt).dx_token;
+        self.dx_usdc = self.market(self.usdc).dx_token;
+
+        let (op_badge, oracle, usdt, usdc) = (self.badges.op, self.oracle, self.usdt, self.usdc);
+        self.call_with_badge(op_badge, oracle, "set_price_quote_in_xrd", manifest_args!(usdt, dec!("20"))).expect_commit_success();
+        self.call_with_badge(op_badge, oracle, "set_price_quote_in_xrd", manifest_args!(usdc, dec!("20"))).expect_commit_success();
+    }
+
+    pub fn execute(&mut self, manifest: TransactionManifestV1, signers: &[&Account]) -> TransactionReceipt{
+        let proofs: Vec<NonFungibleGlobalId> = signers.iter().map(|account| NonFungibleGlobalId::from_public_key(&account.public_key)).collect();
+        self.runner.execute_manifest_ignoring_fee(manifest, proofs)
+    }
+
+    pub fn current_epoch(&mut self) -> u64{
+        self.runner.get_current_epoch().number()
+    }
+
+    pub fn advance_epochs(&mut self, epochs: u64){
+        let epoch = self.current_epoch();
+        self.runner.set_current_epoch(Epoch::of(epoch + epochs));
+    }
+
+    /// A new account with 10000 XRD, funded by the admin with the given stablecoins.
+    pub fn new_account(&mut self, usdt_amount: Decimal, usdc_amount: Decimal) -> Account{
+        let (public_key, _, address) = self.runner.new_allocated_account();
+        let account = Account{public_key, address};
+        let (usdt, usdc) = (self.usdt, self.usdc);
+        if usdt_amount.is_positive() {
+            self.transfer(usdt, usdt_amount, &account);
+        }
+        if usdc_amount.is_positive() {
+            self.transfer(usdc, usdc_amount, &account);
+        }
+        account
+    }
+
+    pub fn transfer(&mut self, token: ResourceAddress, amount: Decimal, to: &Account){
+        let admin = self.admin.clone();
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(admin.address, token, amount)
+            .call_method(to.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[&admin, to]).expect_commit_success();
+    }
+
+    pub fn free_xrd(&mut self, account: &Account){
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_method(FAUCET, "free", manifest_args!())
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account]).expect_commit_success();
+    }
+
+    pub fn balance(&mut self, account: &Account, token: ResourceAddress) -> Decimal{
+        self.runner.get_component_balance(account.address, token)
+    }
+
+    /// Signs the price of XRD in the quote token at the current epoch.
+    pub fn sign_price(&mut self, quote: ResourceAddress, xrd_price_in_quote: &str) -> (String, u64, String){
+        self.timestamp += 1;
+        let encoder = AddressBech32Encoder::new(&NetworkDefinition::simulator());
+        let message = format!(
+            "{base}/{quote}{price}{epoch_at}{timestamp}",
+            base=encoder.encode(&XRD.as_node_id().0).unwrap(),
+            quote=encoder.encode(&quote.as_node_id().0).unwrap(),
+            price=xrd_price_in_quote,
+            epoch_at=self.current_epoch(),
+            timestamp=self.timestamp
+        );
+        let signature = self.signing_key.sign(message.as_bytes());
+        (xrd_price_in_quote.to_string(), self.timestamp, hex::encode(signature.to_bytes()))
+    }
+
+    /// Prices of a CDP whose borrow token or collateral is XRD.
+    pub fn prices(&mut self, quote: ResourceAddress, xrd_price_in_quote: &str) -> Prices{
+        let (price1, timestamp1, signature1) = self.sign_price(quote, xrd_price_in_quote);
+        Prices{
+            price1,
+            quote1: quote,
+            timestamp1,
+            signature1,
+            price2: None,
+            quote2: None,
+            timestamp2: None,
+            signature2: None
+        }
+    }
+
+    /// Prices of a CDP borrowing `quote1` against `quote2`.
+    pub fn price_pair(&mut self, quote1: ResourceAddress, xrd_price_in_quote1: &str, quote2: ResourceAddress, xrd_price_in_quote2: &str) -> Prices{
+        let (price1, timestamp1, signature1) = self.sign_price(quote1, xrd_price_in_quote1);
+        let (price2, timestamp2, signature2) = self.sign_price(quote2, xrd_price_in_quote2);
+        Prices{
+            price1,
+            quote1,
+            timestamp1,
+            signature1,
+            price2: Some(price2),
+            quote2: Some(quote2),
+            timestamp2: Some(timestamp2),
+            signature2: Some(signature2)
+        }
+    }
+
+    /// Calls a method with a proof of the badge held by the admin account.
+    pub fn call_with_badge(&mut self, badge: ResourceAddress, component: ComponentAddress, method: &str, args: impl ResolvableArguments) -> TransactionReceipt{
+        let admin = self.admin.clone();
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .create_proof_from_account_of_amount(admin.address, badge, Decimal::ONE)
+            .call_method(component, method, args)
+            .call_method(admin.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[&admin])
+    }
+
+    /// Queues an admin action on the timelock, the id is the output of the instruction 2.
+    pub fn queue<A: ManifestEncode>(&mut self, target: ComponentAddress, method: &str, args: A, eta_epoch: u64, returns_bucket: bool) -> TransactionReceipt{
+        let (badge, timelock) = (self.badges.admin, self.timelock);
+        self.call_with_badge(badge, timelock, "queue", manifest_args!(target, method.to_string(), args, eta_epoch, returns_bucket))
+    }
+
+    /// Queues an action through the whitelist of a manager, `queue_method` is one of `queue_risk_action`,
+    /// `queue_oracle_action` and `queue_treasury_action`.
+    pub fn queue_by<A: ManifestEncode>(&mut self, badge: ResourceAddress, queue_method: &str, target: ComponentAddress, method: &str, args: A) -> TransactionReceipt{
+        let eta_epoch = self.current_epoch() + TIMELOCK_DELAY;
+        let timelock = self.timelock;
+        self.call_with_badge(badge, timelock, queue_method, manifest_args!(target, method.to_string(), args, eta_epoch))
+    }
+
+    pub fn execute_action(&mut self, badge: ResourceAddress, id: u64) -> TransactionReceipt{
+        let timelock = self.timelock;
+        self.call_with_badge(badge, timelock, "execute", manifest_args!(id))
+    }
+
+    /// Queues the call by the admin, waits for the delay and executes it.
+    pub fn govern<A: ManifestEncode>(&mut self, target: ComponentAddress, method: &str, args: A) -> TransactionReceipt{
+        let eta_epoch = self.current_epoch() + TIMELOCK_DELAY;
+        let id = self.queue(target, method, args, eta_epoch, false).expect_commit_success().output::<u64>(2);
+        self.advance_epochs(TIMELOCK_DELAY);
+        let admin_badge = self.badges.admin;
+        self.execute_action(admin_badge, id)
+    }
+
+    pub fn market(&mut self, token: ResourceAddress) -> MarketInfo{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_method(self.protocol, "get_market", manifest_args!(token))
+            .build();
+        self.execute(manifest, &[]).expect_commit_success().output::<MarketInfo>(1)
+    }
+
+    pub fn cdp_summary_receipt(&mut self, id: u64, borrow_price_in_xrd: Decimal, collateral_price_in_xrd: Decimal) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_method(self.protocol, "get_cdp_summary", manifest_args!(id, borrow_price_in_xrd, collateral_price_in_xrd))
+            .build();
+        self.execute(manifest, &[])
+    }
+
+    pub fn cdp_summary(&mut self, id: u64, borrow_price_in_xrd: Decimal, collateral_price_in_xrd: Decimal) -> CDPSummary{
+        self.cdp_summary_receipt(id, borrow_price_in_xrd, collateral_price_in_xrd).expect_commit_success().output::<CDPSummary>(1)
+    }
+
+    pub fn supply(&mut self, account: &Account, token: ResourceAddress, amount: Decimal) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, token, amount)
+            .take_all_from_worktop(token, "supply")
+            .call_method_with_name_lookup(self.protocol, "supply", |lookup| manifest_args!(lookup.bucket("supply")))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn borrow(&mut self,
+        account: &Account,
+        collateral_token: ResourceAddress,
+        collateral_amount: Decimal,
+        borrow_token: ResourceAddress,
+        borrow_amount: Decimal,
+        is_stable: bool,
+        prices: &Prices
+    ) -> TransactionReceipt{
+        let method = if is_stable { "borrow_stable" } else { "borrow_variable" };
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, collateral_token, collateral_amount)
+            .take_all_from_worktop(collateral_token, "collateral")
+            .call_method_with_name_lookup(self.protocol, method, |lookup| price_args!(prices; lookup.bucket("collateral"), borrow_token, borrow_amount))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn supply_and_borrow(&mut self,
+        account: &Account,
+        token: ResourceAddress,
+        amount: Decimal,
+        collateral_amount: Decimal,
+        borrow_token: ResourceAddress,
+        borrow_amount: Decimal,
+        is_stable: bool,
+        prices: &Prices
+    ) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, token, amount)
+            .take_all_from_worktop(token, "supply")
+            .call_method_with_name_lookup(self.protocol, "supply_and_borrow", |lookup| price_args!(prices;
+                lookup.bucket("supply"), collateral_amount, borrow_token, borrow_amount, is_stable
+            ))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    /// Pops a proof of the CDP held by the account as `cdp_proof`.
+    fn with_cdp_proof(&self, builder: ManifestBuilder, account: &Account, id: u64) -> ManifestBuilder{
+        builder
+            .create_proof_from_account_of_non_fungibles(account.address, self.cdp_res, [NonFungibleLocalId::integer(id)])
+            .pop_from_auth_zone("cdp_proof")
+    }
+
+    fn deposit_and_execute(&mut self, builder: ManifestBuilder, account: &Account) -> TransactionReceipt{
+        let manifest = builder
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn extend_borrow(&mut self, account: &Account, id: u64, amount: Decimal, prices: &Prices) -> TransactionReceipt{
+        let builder = self.with_cdp_proof(ManifestBuilder::new().lock_fee_from_faucet(), account, id)
+            .call_method_with_name_lookup(self.protocol, "extend_borrow", |lookup| price_args!(prices; lookup.proof("cdp_proof"), amount));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn withdraw_collateral(&mut self, account: &Account, id: u64, amount: Decimal, prices: &Prices) -> TransactionReceipt{
+        let builder = self.with_cdp_proof(ManifestBuilder::new().lock_fee_from_faucet(), account, id)
+            .call_method_with_name_lookup(self.protocol, "withdraw_collateral", |lookup| price_args!(prices; lookup.proof("cdp_proof"), amount));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn repay(&mut self, account: &Account, id: u64, token: ResourceAddress, amount: Decimal) -> TransactionReceipt{
+        let builder = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, token, amount)
+            .take_all_from_worktop(token, "repay");
+        let builder = self.with_cdp_proof(builder, account, id)
+            .call_method_with_name_lookup(self.protocol, "repay", |lookup| manifest_args!(lookup.bucket("repay"), lookup.proof("cdp_proof")));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn addition_collateral(&mut self, account: &Account, id: u64, token: ResourceAddress, amount: Decimal) -> TransactionReceipt{
+        let builder = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, token, amount)
+            .take_all_from_worktop(token, "collateral");
+        let builder = self.with_cdp_proof(builder, account, id)
+            .call_method_with_name_lookup(self.protocol, "addition_collateral", |lookup| manifest_args!(lookup.proof("cdp_proof"), lookup.bucket("collateral")));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn repay_with_collateral(&mut self, account: &Account, id: u64, collateral_amount: Decimal, swap_adapter: ComponentAddress, min_out: Decimal, prices: &Prices) -> TransactionReceipt{
+        let builder = self.with_cdp_proof(ManifestBuilder::new().lock_fee_from_faucet(), account, id)
+            .call_method_with_name_lookup(self.protocol, "repay_with_collateral", |lookup| price_args!(prices;
+                lookup.proof("cdp_proof"), collateral_amount, swap_adapter, min_out
+            ));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn split_cdp(&mut self, account: &Account, id: u64, collateral_amount: Decimal, debt_amount: Decimal, prices: &Prices) -> TransactionReceipt{
+        let builder = self.with_cdp_proof(ManifestBuilder::new().lock_fee_from_faucet(), account, id)
+            .call_method_with_name_lookup(self.protocol, "split_cdp", |lookup| price_args!(prices;
+                lookup.proof("cdp_proof"), collateral_amount, debt_amount
+            ));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn merge_cdp(&mut self, account: &Account, id: u64, other_id: u64, prices: &Prices) -> TransactionReceipt{
+        let builder = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_non_fungibles_from_account(account.address, self.cdp_res, [NonFungibleLocalId::integer(other_id)])
+            .take_all_from_worktop(self.cdp_res, "other_cdp");
+        let builder = self.with_cdp_proof(builder, account, id)
+            .call_method_with_name_lookup(self.protocol, "merge_cdp", |lookup| price_args!(prices; lookup.proof("cdp_proof"), lookup.bucket("other_cdp")));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn swap_collateral(&mut self, account: &Account, id: u64, token: ResourceAddress, amount: Decimal, prices: &Prices) -> TransactionReceipt{
+        let builder = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, token, amount)
+            .take_all_from_worktop(token, "collateral");
+        let builder = self.with_cdp_proof(builder, account, id)
+            .call_method_with_name_lookup(self.protocol, "swap_collateral", |lookup| price_args!(prices; lookup.proof("cdp_proof"), lookup.bucket("collateral")));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn switch_rate_mode(&mut self, account: &Account, id: u64) -> TransactionReceipt{
+        let builder = self.with_cdp_proof(ManifestBuilder::new().lock_fee_from_faucet(), account, id)
+            .call_method_with_name_lookup(self.protocol, "switch_rate_mode", |lookup| manifest_args!(lookup.proof("cdp_proof")));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn set_emode(&mut self, account: &Account, id: u64, emode_category: u8, prices: &Prices) -> TransactionReceipt{
+        let builder = self.with_cdp_proof(ManifestBuilder::new().lock_fee_from_faucet(), account, id)
+            .call_method_with_name_lookup(self.protocol, "set_emode", |lookup| price_args!(prices; lookup.proof("cdp_proof"), emode_category));
+        self.deposit_and_execute(builder, account)
+    }
+
+    pub fn close_cdp(&mut self, account: &Account, id: u64, repay_token: ResourceAddress, repay_amount: Decimal, withdraw_underlying: bool) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_non_fungibles_from_account(account.address, self.cdp_res, [NonFungibleLocalId::integer(id)])
+            .take_all_from_worktop(self.cdp_res, "cdp")
+            .withdraw_from_account(account.address, repay_token, repay_amount)
+            .take_all_from_worktop(repay_token, "repay")
+            .call_method_with_name_lookup(self.protocol, "close_cdp", |lookup| manifest_args!(lookup.bucket("cdp"), lookup.bucket("repay"), withdraw_underlying))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn liquidation(&mut self, account: &Account, debt_token: ResourceAddress, amount: Decimal, debt_to_cover: Decimal, id: u64, receive_dx: bool, prices: &Prices) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, debt_token, amount)
+            .take_all_from_worktop(debt_token, "debt")
+            .call_method_with_name_lookup(self.protocol, "liquidation", |lookup| price_args!(prices;
+                lookup.bucket("debt"), debt_to_cover, id, receive_dx
+            ))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn liquidate_batch(&mut self, account: &Account, debt_token: ResourceAddress, amount: Decimal, liquidations: Vec<(u64, Decimal)>, receive_dx: bool, prices: &Prices) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, debt_token, amount)
+            .take_all_from_worktop(debt_token, "debt")
+            .call_method_with_name_lookup(self.protocol, "liquidate_batch", |lookup| price_args!(prices;
+                lookup.bucket("debt"), liquidations, receive_dx
+            ))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn flash_liquidate(&mut self, account: &Account, id: u64, debt_to_cover: Decimal, swap_adapter: ComponentAddress, min_profit: Decimal, prices: &Prices) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .call_method(self.protocol, "flash_liquidate", price_args!(prices; id, debt_to_cover, swap_adapter, min_profit))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn open_leveraged(&mut self,
+        account: &Account,
+        collateral_token: ResourceAddress,
+        amount: Decimal,
+        borrow_token: ResourceAddress,
+        target_leverage: Decimal,
+        swap_adapter: ComponentAddress,
+        min_out: Decimal,
+        prices: &Prices
+    ) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, collateral_token, amount)
+            .take_all_from_worktop(collateral_token, "collateral")
+            .call_method_with_name_lookup(self.protocol, "open_leveraged", |lookup| price_args!(prices;
+                lookup.bucket("collateral"), borrow_token, target_leverage, swap_adapter, min_out
+            ))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    /// Rebalances the stable rate of the CDP with the keeper badge of the admin.
+    pub fn rebalance_stable_rate(&mut self, id: u64) -> TransactionReceipt{
+        let (keeper, protocol) = (self.badges.keeper, self.protocol);
+        self.call_with_badge(keeper, protocol, "rebalance_stable_rate", manifest_args!(id))
+    }
+
+    pub fn join(&mut self, account: &Account, validator: ComponentAddress, amount: Decimal) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, XRD, amount)
+            .take_all_from_worktop(XRD, "xrd")
+            .call_method_with_name_lookup(self.protocol, "join", |lookup| manifest_args!(validator, lookup.bucket("xrd")))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    pub fn redeem(&mut self, account: &Account, validator: ComponentAddress, token: ResourceAddress, amount: Decimal, is_faster: bool) -> TransactionReceipt{
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(account.address, token, amount)
+            .take_all_from_worktop(token, "redeem")
+            .call_method_with_name_lookup(self.protocol, "redeem", |lookup| manifest_args!(validator, lookup.bucket("redeem"), is_faster))
+            .call_method(account.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+            .build();
+        self.execute(manifest, &[account])
+    }
+
+    /// A constant-product adapter of XRD and the stablecoin funded by the admin, approved through the timelock.
+    pub fn new_swap_adapter(&mut self, token: ResourceAddress, xrd_amount: Decimal, token_amount: Decimal, approved: bool) -> ComponentAddress{
+        let admin = self.admin.clone();
+        self.free_xrd(&admin);
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .withdraw_from_account(admin.address, XRD, xrd_amount)
+            .take_all_from_worktop(XRD, "xrd")
+            .withdraw_from_account(admin.address, token, token_amount)
+            .take_all_from_worktop(token, "token")
+            .call_function_with_name_lookup(self.package, "MockSwapAdapter", "instantiate", |lookup| manifest_args!(
+                lookup.bucket("xrd"), lookup.bucket("token"), dec!("0.003")
+            ))
+            .build();
+        let swap_adapter = self.execute(manifest, &[&admin]).expect_commit_success().new_component_addresses()[0];
+        if approved {
+            let protocol = self.protocol;
+            self.govern(protocol, "set_swap_adapter", (swap_adapter, true)).expect_commit_success();
+        }
+        swap_adapter
+    }
+
+    /// A validator owned by the account, which accepts the delegated stake of the staking pool.
+    pub fn new_validator(&mut self, owner: &Account) -> ComponentAddress{
+        let validator = self.runner.new_validator_with_pub_key(owner.public_key, owner.address);
+        let manifest = ManifestBuilder::new()
+            .lock_fee_from_faucet()
+            .create_proof_from_account_of_amount(owner.address, VALIDATOR_OWNER_BADGE, Decimal::ONE)
+            .call_method(validator, "update_accept_delegated_stake", manifest_args!(true))
+            .build();
+        self.execute(manifest, &[owner]).expect_commit_success();
+        validator
+    }
+
+    /// Opens a borrower CDP of `usdt_amount` USDT against 1000 XRD, the lender supplies the USDT.
+    pub fn open_xrd_cdp(&mut self, lender: &Account, borrower: &Account, usdt_amount: Decimal, is_stable: bool) -> TransactionReceipt{
+        let usdt = self.usdt;
+        if self.market(usdt).available_liquidity < usdt_amount {
+            self.supply(lender, usdt, dec!("1000")).expect_commit_success();
+        }
+        let prices = self.prices(usdt, XRD_PRICE);
+        self.borrow(borrower, XRD, dec!("1000"), usdt, usdt_amount, is_stable, &prices)
+    }
+}
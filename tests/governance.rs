@@ -0,0 +1,193 @@
+mod common;
+
+use scrypto::prelude::*;
+use transaction::builder::ManifestBuilder;
+use dexian_lending_protocol::risk::*;
+use common::*;
+
+#[test]
+fn test_update_risk_parameters(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_ltv", (XRD, dec!("0.5"))).expect_commit_success();
+    env.govern(cdp_mgr, "set_liquidation_bonus", (XRD, dec!("0.05"))).expect_commit_success();
+    env.govern(cdp_mgr, "set_interest_model", (usdt, InterestModel::Default)).expect_commit_success();
+    let market = env.market(XRD);
+    assert_eq!(market.ltv, dec!("0.5"));
+    assert_eq!(market.liquidation_bonus, dec!("0.05"));
+
+    // the LTV must stay below the liquidation threshold
+    expect_failure(
+        env.govern(cdp_mgr, "set_ltv", (XRD, dec!("0.8"))),
+        "The LTV must be positive and less than the liquidation threshold."
+    );
+
+    // 1000 XRD back 25 USDT at the new LTV
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("26"), false).expect_commit_failure();
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+}
+
+#[test]
+fn test_parameter_ramp(){
+    let ramp = ParameterRamp{
+        start_value: dec!("0.8"),
+        target_value: dec!("0.6"),
+        start_epoch: 100u64,
+        end_epoch: 200u64
+    };
+    assert_eq!(ramp.get_value(90u64), dec!("0.8"));
+    assert_eq!(ramp.get_value(100u64), dec!("0.8"));
+    assert_eq!(ramp.get_value(150u64), dec!("0.7"));
+    assert_eq!(ramp.get_value(200u64), dec!("0.6"));
+    assert_eq!(ramp.get_value(250u64), dec!("0.6"));
+    assert!(ramp.is_in_progress(150u64));
+    assert!(!ramp.is_in_progress(200u64));
+}
+
+#[test]
+fn test_ramp_and_guardian_cancel(){
+    let mut env = Env::new();
+    let (cdp_mgr, guardian_badge, op_badge) = (env.cdp_mgr, env.badges.guardian, env.badges.op);
+    env.govern(cdp_mgr, "ramp_risk_params", (XRD, dec!("0.4"), dec!("0.5"), 100u64)).expect_commit_success();
+
+    // halfway from 0.6/0.7 to 0.4/0.5
+    env.advance_epochs(50u64);
+    let market = env.market(XRD);
+    assert_eq!(market.ltv, dec!("0.5"));
+    assert_eq!(market.liquidation_threshold, dec!("0.6"));
+
+    // the parameters under a ramp can not be set directly
+    expect_failure(env.govern(cdp_mgr, "set_ltv", (XRD, dec!("0.3"))), "A ramp of the risk parameters is in progress.");
+    assert_eq!(env.market(XRD).ltv, dec!("0.48"));
+
+    // the guardian stops the ramp without the delay of the timelock, the values of the current epoch are kept
+    env.call_with_badge(op_badge, cdp_mgr, "cancel_ramp", manifest_args!(XRD)).expect_commit_failure();
+    env.call_with_badge(guardian_badge, cdp_mgr, "cancel_ramp", manifest_args!(XRD)).expect_commit_success();
+    env.advance_epochs(100u64);
+    let market = env.market(XRD);
+    assert_eq!(market.ltv, dec!("0.48"));
+    assert_eq!(market.liquidation_threshold, dec!("0.58"));
+    assert!(market.ltv_ramp.is_none() && market.liquidation_threshold_ramp.is_none());
+}
+
+#[test]
+fn test_timelock_queue_cancel_and_execute(){
+    let mut env = Env::new();
+    let (cdp_mgr, timelock) = (env.cdp_mgr, env.timelock);
+    let (admin_badge, guardian_badge) = (env.badges.admin, env.badges.guardian);
+
+    // the privileged methods only accept calls from the timelock
+    env.call_with_badge(admin_badge, cdp_mgr, "set_ltv", manifest_args!(XRD, dec!("0.5"))).expect_commit_failure();
+
+    let current_epoch = env.current_epoch();
+    expect_failure(
+        env.queue(cdp_mgr, "set_ltv", (XRD, dec!("0.5")), current_epoch + TIMELOCK_DELAY - 1, false),
+        "The execution epoch does not satisfy the minimum delay."
+    );
+    let id = env.queue(cdp_mgr, "set_ltv", (XRD, dec!("0.5")), current_epoch + TIMELOCK_DELAY, false).expect_commit_success().output::<u64>(2);
+    expect_failure(env.execute_action(admin_badge, id), "The action is still locked.");
+
+    // a cancelled action can not be executed after the delay
+    env.call_with_badge(guardian_badge, timelock, "cancel", manifest_args!(id)).expect_commit_success();
+    env.advance_epochs(TIMELOCK_DELAY);
+    expect_failure(env.execute_action(admin_badge, id), "The action does not exist.");
+    assert_eq!(env.market(XRD).ltv, dec!("0.6"));
+
+    env.govern(cdp_mgr, "set_ltv", (XRD, dec!("0.5"))).expect_commit_success();
+    assert_eq!(env.market(XRD).ltv, dec!("0.5"));
+}
+
+#[test]
+fn test_manager_roles(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    let risk_manager_badge = env.badges.risk_manager;
+
+    // the risk manager only queues the risk management methods
+    let id = env.queue_by(risk_manager_badge, "queue_risk_action", cdp_mgr, "set_caps", (usdt, dec!("1500"), dec!("30")))
+        .expect_commit_success().output::<u64>(2);
+    expect_failure(
+        env.queue_by(risk_manager_badge, "queue_risk_action", cdp_mgr, "new_pool", (18u8, usdt)),
+        "The method is not a risk management method."
+    );
+    env.advance_epochs(TIMELOCK_DELAY);
+    env.execute_action(risk_manager_badge, id).expect_commit_success();
+    let market = env.market(usdt);
+    assert_eq!(market.supply_cap, dec!("1500"));
+    assert_eq!(market.borrow_cap, dec!("30"));
+
+    // the cdp manager is only called through the protocol
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .withdraw_from_account(lender.address, usdt, dec!("100"))
+        .take_all_from_worktop(usdt, "supply")
+        .call_method_with_name_lookup(cdp_mgr, "supply", |lookup| manifest_args!(lookup.bucket("supply")))
+        .call_method(lender.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+        .build();
+    env.execute(manifest, &[&lender]).expect_commit_failure();
+}
+
+#[test]
+fn test_guardian_pause(){
+    let mut env = Env::new();
+    let (usdt, protocol) = (env.usdt, env.protocol);
+    let (guardian_badge, op_badge) = (env.badges.guardian, env.badges.op);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    env.call_with_badge(op_badge, protocol, "pause", manifest_args!()).expect_commit_failure();
+    env.call_with_badge(guardian_badge, protocol, "pause", manifest_args!()).expect_commit_success();
+    expect_failure(env.supply(&lender, usdt, dec!("100")), "The protocol is paused.");
+
+    // the positions can still be liquidated while the protocol is paused
+    let prices = env.prices(usdt, "0.03");
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 1u64, false, &prices).expect_commit_success();
+
+    // only the admin resumes the protocol, through the timelock
+    env.call_with_badge(guardian_badge, protocol, "unpause", manifest_args!()).expect_commit_failure();
+    env.govern(protocol, "unpause", ()).expect_commit_success();
+    env.supply(&lender, usdt, dec!("100")).expect_commit_success();
+}
+
+#[test]
+fn test_keeper_reward_and_treasury_withdrawal(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr, protocol, timelock) = (env.usdt, env.cdp_mgr, env.protocol, env.timelock);
+    let treasurer_badge = env.badges.treasurer;
+    let admin = env.admin.clone();
+    env.free_xrd(&admin);
+    env.govern(cdp_mgr, "set_rebalance_threshold", (usdt, Decimal::ONE, Decimal::ZERO)).expect_commit_success();
+    env.govern(protocol, "set_keeper_reward", (Decimal::ONE,)).expect_commit_success();
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .withdraw_from_account(admin.address, XRD, dec!("5"))
+        .take_all_from_worktop(XRD, "reward")
+        .call_method_with_name_lookup(protocol, "fund_keeper_reward", |lookup| manifest_args!(lookup.bucket("reward")))
+        .build();
+    env.execute(manifest, &[&admin]).expect_commit_success();
+
+    let lender = env.new_account(dec!("100"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdt, dec!("100")).expect_commit_success();
+    env.open_xrd_cdp(&lender, &borrower, dec!("10"), true).expect_commit_success();
+    env.open_xrd_cdp(&lender, &other_borrower, dec!("20"), false).expect_commit_success();
+
+    // the keeper badge is held by the admin account, which receives the reward
+    let xrd_before = env.balance(&admin, XRD);
+    env.rebalance_stable_rate(1u64).expect_commit_success();
+    assert_eq!(env.balance(&admin, XRD) - xrd_before, Decimal::ONE);
+
+    // the treasurer withdraws the rest of the keeper fund
+    let id = env.queue_by(treasurer_badge, "queue_treasury_action", protocol, "withdraw_keeper_fund", (dec!("4"),))
+        .expect_commit_success().output::<u64>(2);
+    env.advance_epochs(TIMELOCK_DELAY);
+    let xrd_before = env.balance(&admin, XRD);
+    env.call_with_badge(treasurer_badge, timelock, "execute_withdrawal", manifest_args!(id)).expect_commit_success();
+    assert_eq!(env.balance(&admin, XRD) - xrd_before, dec!("4"));
+}
@@ -1,6 +1,7 @@
 use scrypto::prelude::*;
 use scrypto_unit::*;
 use transaction::builder::ManifestBuilder;
+use dexian_lending_protocol::risk::*;
 
 #[test]
 fn test_hello() {
@@ -74,3 +75,54 @@ fn test_verify_ed25519(){
     //     }
     // }
 }
+
+#[test]
+fn test_assert_risk_params(){
+    assert_risk_params(dec!("0.6"), dec!("0.7"), dec!("0.07"));
+    // 0.9 * (1 + 0.11) = 0.999
+    assert_risk_params(dec!("0.85"), dec!("0.9"), dec!("0.11"));
+}
+
+#[test]
+#[should_panic(expected = "The LTV must be positive and less than the liquidation threshold.")]
+fn test_assert_risk_params_ltv_above_threshold(){
+    assert_risk_params(dec!("0.8"), dec!("0.7"), dec!("0.05"));
+}
+
+#[test]
+#[should_panic(expected = "The liquidation threshold must be less than ONE.")]
+fn test_assert_risk_params_threshold_of_one(){
+    assert_risk_params(dec!("0.8"), Decimal::ONE, Decimal::ZERO);
+}
+
+#[test]
+#[should_panic(expected = "The liquidation threshold with the bonus must not exceed ONE.")]
+fn test_assert_risk_params_bonus_too_large(){
+    assert_risk_params(dec!("0.9"), dec!("0.95"), dec!("0.1"));
+}
+
+#[test]
+fn test_position_risk(){
+    // 200 collateral * 1 XRD against 100 debt * 1 XRD
+    let risk = PositionRisk::new(dec!("100"), Decimal::ONE, dec!("200"), Decimal::ONE, dec!("0.6"), dec!("0.7"), 18u8);
+    assert_eq!(risk.borrow_capacity, dec!("120"));
+    assert_eq!(risk.health_factor, dec!("1.4"));
+    assert!(!risk.is_liquidatable());
+    risk.assert_borrowable();
+
+    let risk = PositionRisk::new(dec!("150"), Decimal::ONE, dec!("200"), Decimal::ONE, dec!("0.6"), dec!("0.7"), 18u8);
+    assert!(risk.health_factor < Decimal::ONE);
+    assert!(risk.is_liquidatable());
+
+    // 100 XRD * 0.6 / 7 = 8.5714..., floored to the divisibility of the borrow token
+    let risk = PositionRisk::new(Decimal::ZERO, dec!("7"), dec!("100"), Decimal::ONE, dec!("0.6"), dec!("0.7"), 2u8);
+    assert_eq!(risk.borrow_capacity, dec!("8.57"));
+    assert_eq!(risk.health_factor, Decimal::MAX);
+    assert!(!risk.is_liquidatable());
+}
+
+#[test]
+#[should_panic(expected = "Insufficient remaining collateral.")]
+fn test_position_risk_insufficient_collateral(){
+    PositionRisk::new(dec!("121"), Decimal::ONE, dec!("200"), Decimal::ONE, dec!("0.6"), dec!("0.7"), 18u8).assert_collateral_sufficient();
+}
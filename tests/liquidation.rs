@@ -0,0 +1,215 @@
+mod common;
+
+use scrypto::prelude::*;
+use dexian_lending_protocol::risk::*;
+use common::*;
+
+#[test]
+fn test_close_factor(){
+    // the critical health factor defaults to ZERO, so unhealthy positions are liquidated partially
+    assert_eq!(get_close_factor_percent(dec!("50"), Decimal::ZERO, Decimal::ZERO, dec!("0.5"), dec!("100")), dec!("50"));
+    // below the critical health factor
+    assert_eq!(get_close_factor_percent(dec!("50"), dec!("0.95"), Decimal::ZERO, dec!("0.9"), dec!("100")), Decimal::ONE_HUNDRED);
+    assert_eq!(get_close_factor_percent(dec!("50"), dec!("0.95"), Decimal::ZERO, dec!("0.97"), dec!("100")), dec!("50"));
+    // the remaining 7.5 would be dust
+    assert_eq!(get_close_factor_percent(dec!("50"), Decimal::ZERO, dec!("10"), dec!("0.99"), dec!("15")), Decimal::ONE_HUNDRED);
+}
+
+#[test]
+fn test_split_liquidation_bonus(){
+    // 110 underlying seized with a 10% bonus: the bonus is 10, the protocol keeps 20% of it
+    let (protocol_fee_collateral, liquidator_bonus) = split_liquidation_bonus(dec!("110"), dec!("0.1"), dec!("0.2"), Decimal::ONE, 18u8);
+    assert_eq!(protocol_fee_collateral, dec!("2"));
+    assert_eq!(liquidator_bonus, dec!("8"));
+
+    // the protocol fee is paid in dx token: 2 / 1.25
+    let (protocol_fee_collateral, liquidator_bonus) = split_liquidation_bonus(dec!("110"), dec!("0.1"), dec!("0.2"), dec!("1.25"), 18u8);
+    assert_eq!(protocol_fee_collateral, dec!("1.6"));
+    assert_eq!(liquidator_bonus, dec!("8"));
+
+    // the rounding of the dx token goes to the liquidator: floor(2 / 1.5) = 1 dx, 10 - 1 * 1.5
+    let (protocol_fee_collateral, liquidator_bonus) = split_liquidation_bonus(dec!("110"), dec!("0.1"), dec!("0.2"), dec!("1.5"), 0u8);
+    assert_eq!(protocol_fee_collateral, Decimal::ONE);
+    assert_eq!(liquidator_bonus, dec!("8.5"));
+
+    let (protocol_fee_collateral, liquidator_bonus) = split_liquidation_bonus(dec!("110"), dec!("0.1"), Decimal::ZERO, Decimal::ONE, 18u8);
+    assert_eq!(protocol_fee_collateral, Decimal::ZERO);
+    assert_eq!(liquidator_bonus, dec!("10"));
+}
+
+#[test]
+fn test_liquidation_capped_by_close_factor(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    // 1 USDT = 33.3 XRD, the health factor drops to 0.84
+    let prices = env.prices(usdt, "0.03");
+    let xrd_before = env.balance(&liquidator, XRD);
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 1u64, false, &prices).expect_commit_success();
+
+    // half of the debt is repaid by default, the rest of the debt bucket is refunded
+    assert_approx(env.balance(&liquidator, usdt), dec!("87.5"));
+    let seized = dec!("12.5") * price_in_xrd("0.03") * dec!("1.07");
+    assert_approx(env.balance(&liquidator, XRD) - xrd_before, seized);
+    let summary = env.cdp_summary(1u64, price_in_xrd("0.03"), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("12.5"));
+    assert_approx(summary.collateral_amount, dec!("1000") - seized);
+}
+
+#[test]
+fn test_liquidation_below_critical_health_factor(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_close_factor", (usdt, dec!("50"), dec!("0.9"), Decimal::ZERO)).expect_commit_success();
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    // the health factor of 0.84 is below the critical 0.9, the whole debt can be liquidated
+    let prices = env.prices(usdt, "0.03");
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 1u64, false, &prices).expect_commit_success();
+    assert_approx(env.balance(&liquidator, usdt), dec!("75"));
+    let summary = env.cdp_summary(1u64, price_in_xrd("0.03"), Decimal::ONE);
+    assert_approx(summary.debt_amount, Decimal::ZERO);
+}
+
+#[test]
+fn test_liquidation_leaving_dust(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_close_factor", (usdt, dec!("50"), Decimal::ZERO, dec!("20"))).expect_commit_success();
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    // the 12.5 left by the close factor is below the dust threshold of 20
+    let prices = env.prices(usdt, "0.03");
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 1u64, false, &prices).expect_commit_success();
+    let summary = env.cdp_summary(1u64, price_in_xrd("0.03"), Decimal::ONE);
+    assert_approx(summary.debt_amount, Decimal::ZERO);
+}
+
+#[test]
+fn test_liquidation_protocol_fee(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_liquidation_protocol_fee", (XRD, dec!("0.2"))).expect_commit_success();
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    let insurance_before = env.market(XRD).insurance_balance;
+    let xrd_before = env.balance(&liquidator, XRD);
+    let prices = env.prices(usdt, "0.03");
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 1u64, false, &prices).expect_commit_success();
+
+    // 12.5 USDT of debt seizes 445.83 XRD, the protocol keeps 20% of the 29.17 XRD bonus
+    let seized = dec!("12.5") * price_in_xrd("0.03") * dec!("1.07");
+    let protocol_fee = dec!("12.5") * price_in_xrd("0.03") * dec!("0.07") * dec!("0.2");
+    assert_approx(env.market(XRD).insurance_balance - insurance_before, protocol_fee);
+    assert_approx(env.balance(&liquidator, XRD) - xrd_before, seized - protocol_fee);
+    let summary = env.cdp_summary(1u64, price_in_xrd("0.03"), Decimal::ONE);
+    assert_approx(summary.collateral_amount, dec!("1000") - seized);
+}
+
+#[test]
+fn test_flash_liquidation(){
+    let mut env = Env::new();
+    let (usdt, protocol, guardian_badge) = (env.usdt, env.protocol, env.badges.guardian);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+    // the pool sells USDT for 30 XRD while the oracle quotes 33.3 XRD
+    let swap_adapter = env.new_swap_adapter(usdt, dec!("7200"), dec!("240"), true);
+
+    let prices = env.prices(usdt, "0.03");
+    env.flash_liquidate(&liquidator, 1u64, dec!("12.5"), swap_adapter, dec!("5"), &prices).expect_commit_failure();
+
+    // the liquidator holds no USDT, the pause does not block the liquidations
+    env.call_with_badge(guardian_badge, protocol, "pause", manifest_args!()).expect_commit_success();
+    let prices = env.prices(usdt, "0.03");
+    env.flash_liquidate(&liquidator, 1u64, dec!("12.5"), swap_adapter, Decimal::ONE, &prices).expect_commit_success();
+
+    // the seized XRD are swapped to repay the flashloan of 12.5 USDT and its fee
+    let seized = dec!("12.5") * price_in_xrd("0.03") * dec!("1.07");
+    let input_with_fee = seized * dec!("0.997");
+    let swap_output = dec!("240") * input_with_fee / (dec!("7200") + input_with_fee);
+    let profit = env.balance(&liquidator, usdt);
+    assert!(profit >= Decimal::ONE);
+    assert_approx(profit, swap_output - dec!("12.5125"));
+    let summary = env.cdp_summary(1u64, price_in_xrd("0.03"), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("12.5"));
+    assert_approx(summary.collateral_amount, dec!("1000") - seized);
+}
+
+#[test]
+fn test_liquidate_batch(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let healthy_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+    env.open_xrd_cdp(&lender, &other_borrower, dec!("25"), false).expect_commit_success();
+    env.open_xrd_cdp(&lender, &healthy_borrower, dec!("15"), false).expect_commit_success();
+
+    // at 1 USDT = 33.3 XRD the CDP#3 stays healthy(1.4) and the CDP#9 does not exist, both are skipped
+    let prices = env.prices(usdt, "0.03");
+    let xrd_before = env.balance(&liquidator, XRD);
+    env.liquidate_batch(&liquidator, usdt, dec!("100"), vec![(1u64, Decimal::ZERO), (3u64, Decimal::ZERO), (9u64, Decimal::ZERO), (2u64, Decimal::ZERO)], false, &prices)
+        .expect_commit_success();
+
+    // half of each debt is covered, the seized XRD are aggregated and the rest of the debt bucket is refunded
+    let seized = dec!("12.5") * price_in_xrd("0.03") * dec!("1.07");
+    assert_approx(env.balance(&liquidator, XRD) - xrd_before, seized * dec!("2"));
+    assert_approx(env.balance(&liquidator, usdt), dec!("75"));
+    for id in [1u64, 2u64] {
+        let summary = env.cdp_summary(id, price_in_xrd("0.03"), Decimal::ONE);
+        assert_approx(summary.debt_amount, dec!("12.5"));
+        assert_approx(summary.collateral_amount, dec!("1000") - seized);
+    }
+    let summary = env.cdp_summary(3u64, price_in_xrd("0.03"), Decimal::ONE);
+    assert_approx(summary.debt_amount, dec!("15"));
+    assert_approx(summary.collateral_amount, dec!("1000"));
+}
+
+#[test]
+fn test_liquidation_receiving_dx(){
+    let mut env = Env::new();
+    let (usdt, usdc, dx_xrd) = (env.usdt, env.usdc, env.dx_xrd);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let xrd_borrower = env.new_account(Decimal::ZERO, dec!("100"));
+    let liquidator = env.new_account(dec!("100"), Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+    env.open_xrd_cdp(&lender, &other_borrower, dec!("25"), false).expect_commit_success();
+    let seized = dec!("12.5") * price_in_xrd("0.03") * dec!("1.07");
+
+    // the liquidator asks for the dx token
+    let xrd_before = env.balance(&liquidator, XRD);
+    let prices = env.prices(usdt, "0.03");
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 1u64, true, &prices).expect_commit_success();
+    assert_approx(env.balance(&liquidator, dx_xrd), seized);
+    assert_eq!(env.balance(&liquidator, XRD), xrd_before);
+
+    // 1600 of the 2000 XRD in the pool are borrowed, the seized collateral falls back to the dx token
+    let prices = env.prices(usdc, XRD_PRICE);
+    env.borrow(&xrd_borrower, usdc, dec!("100"), XRD, dec!("1600"), false, &prices).expect_commit_success();
+    assert!(env.market(XRD).available_liquidity < seized);
+    let prices = env.prices(usdt, "0.03");
+    env.liquidation(&liquidator, usdt, dec!("100"), Decimal::ZERO, 2u64, false, &prices).expect_commit_success();
+    assert_approx(env.balance(&liquidator, dx_xrd), seized * dec!("2"));
+    assert_eq!(env.balance(&liquidator, XRD), xrd_before);
+    assert_approx(env.balance(&liquidator, usdt), dec!("75"));
+}
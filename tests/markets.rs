@@ -0,0 +1,170 @@
+mod common;
+
+use scrypto::prelude::*;
+use transaction::builder::ManifestBuilder;
+use dexian_lending_protocol::cdp::EModeCategory;
+use common::*;
+
+#[test]
+fn test_market_and_cdp_queries(){
+    let mut env = Env::new();
+    let (usdt, dx_usdt) = (env.usdt, env.dx_usdt);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+
+    let market = env.market(usdt);
+    assert_eq!(market.underlying_token, usdt);
+    assert_eq!(market.dx_token, dx_usdt);
+    assert_approx(market.total_supply, dec!("1000"));
+    assert_approx(market.total_variable_borrow, dec!("25"));
+    assert_approx(market.utilization, dec!("0.025"));
+    assert_approx(market.available_liquidity, dec!("975"));
+    assert_eq!(market.ltv, dec!("0.85"));
+    assert_eq!(market.supply_cap, Decimal::ZERO);
+    // the dx token resolves to the same market
+    assert_approx(env.market(dx_usdt).total_supply, dec!("1000"));
+
+    // 25 USDT(500 XRD) against 1000 XRD
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_eq!(summary.borrow_token, usdt);
+    assert_eq!(summary.collateral_token, XRD);
+    assert_approx(summary.debt_amount, dec!("25"));
+    assert_approx(summary.accrued_interest, Decimal::ZERO);
+    assert_approx(summary.debt_value, dec!("500"));
+    assert_approx(summary.collateral_value, dec!("1000"));
+    assert_approx(summary.health_factor, dec!("1.4"));
+    assert_approx(summary.max_borrowable, dec!("5"));
+    assert_approx(summary.max_withdrawable, dec!("1000") - dec!("500") / dec!("0.6"));
+    assert_approx(summary.liquidation_price, dec!("500") / dec!("700"));
+}
+
+#[test]
+fn test_supply_and_borrow_caps(){
+    let mut env = Env::new();
+    let (usdt, cdp_mgr) = (env.usdt, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_caps", (usdt, dec!("1500"), dec!("30"))).expect_commit_success();
+    let lender = env.new_account(dec!("2000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+
+    env.supply(&lender, usdt, dec!("1000")).expect_commit_success();
+    expect_failure(env.supply(&lender, usdt, dec!("600")), "The supply cap of the pool has been reached.");
+
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+    expect_failure(env.open_xrd_cdp(&lender, &other_borrower, dec!("10"), false), "The borrow cap of the pool has been reached.");
+    expect_failure(env.open_xrd_cdp(&lender, &other_borrower, dec!("10"), true), "The borrow cap of the pool has been reached.");
+    env.open_xrd_cdp(&lender, &other_borrower, dec!("5"), true).expect_commit_success();
+
+    let market = env.market(usdt);
+    assert_eq!(market.supply_cap, dec!("1500"));
+    assert_eq!(market.borrow_cap, dec!("30"));
+}
+
+#[test]
+fn test_isolation_mode(){
+    let mut env = Env::new();
+    let (usdt, usdc, cdp_mgr) = (env.usdt, env.usdc, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_isolation", (XRD, true, dec!("50"))).expect_commit_success();
+    env.govern(cdp_mgr, "set_borrowable_in_isolation", (usdt, true)).expect_commit_success();
+    let lender = env.new_account(dec!("1000"), dec!("1000"));
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdt, dec!("1000")).expect_commit_success();
+    env.supply(&lender, usdc, dec!("1000")).expect_commit_success();
+
+    let usdt_prices = env.prices(usdt, XRD_PRICE);
+    env.borrow(&borrower, XRD, dec!("2000"), usdt, dec!("30"), false, &usdt_prices).expect_commit_success();
+    let usdc_prices = env.prices(usdc, XRD_PRICE);
+    expect_failure(
+        env.borrow(&other_borrower, XRD, dec!("2000"), usdc, dec!("20"), false, &usdc_prices),
+        "The asset can not be borrowed against isolated collateral."
+    );
+
+    // the ceiling is shared by the stablecoins borrowed against XRD
+    env.govern(cdp_mgr, "set_borrowable_in_isolation", (usdc, true)).expect_commit_success();
+    let usdc_prices = env.prices(usdc, XRD_PRICE);
+    expect_failure(
+        env.borrow(&other_borrower, XRD, dec!("2000"), usdc, dec!("30"), false, &usdc_prices),
+        "The debt ceiling of the isolated collateral has been reached."
+    );
+    env.borrow(&other_borrower, XRD, dec!("2000"), usdc, dec!("20"), false, &usdc_prices).expect_commit_success();
+    let usdt_prices = env.prices(usdt, XRD_PRICE);
+    expect_failure(env.extend_borrow(&borrower, 1u64, Decimal::ONE, &usdt_prices), "The debt ceiling of the isolated collateral has been reached.");
+
+    // the repaid principal frees the ceiling
+    env.repay(&borrower, 1u64, usdt, dec!("10")).expect_commit_success();
+    let market = env.market(XRD);
+    assert_approx(*market.isolated_debts.get(&usdt).unwrap(), dec!("20"));
+    assert_eq!(*market.isolated_debts.get(&usdc).unwrap(), dec!("20"));
+    let usdt_prices = env.prices(usdt, XRD_PRICE);
+    env.extend_borrow(&borrower, 1u64, dec!("10"), &usdt_prices).expect_commit_success();
+}
+
+#[test]
+fn test_emode(){
+    let mut env = Env::new();
+    let (usdt, usdc, cdp_mgr) = (env.usdt, env.usdc, env.cdp_mgr);
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, dec!("100"));
+    env.supply(&lender, usdt, dec!("1000")).expect_commit_success();
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    env.borrow(&borrower, usdc, dec!("100"), usdt, dec!("80"), false, &prices).expect_commit_success();
+
+    // 100 USDC back 85 USDT outside of the e-mode
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    env.extend_borrow(&borrower, 1u64, dec!("12"), &prices).expect_commit_failure();
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    expect_failure(env.set_emode(&borrower, 1u64, 1u8, &prices), "unknow e-mode category.");
+
+    env.govern(cdp_mgr, "set_emode_category", (1u8, "USD".to_string(), dec!("0.95"), dec!("0.97"), dec!("0.01"), 0u64)).expect_commit_success();
+    env.govern(cdp_mgr, "set_asset_emode_category", (usdt, 1u8, 0u64)).expect_commit_success();
+    env.govern(cdp_mgr, "set_asset_emode_category", (usdc, 1u8, 0u64)).expect_commit_success();
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    env.set_emode(&borrower, 1u64, 1u8, &prices).expect_commit_success();
+    let prices = env.price_pair(usdt, XRD_PRICE, usdc, XRD_PRICE);
+    env.extend_borrow(&borrower, 1u64, dec!("12"), &prices).expect_commit_success();
+    assert_approx(env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), price_in_xrd(XRD_PRICE)).debt_amount, dec!("92"));
+
+    // lowering the category and leaving it are ramped
+    expect_failure(
+        env.govern(cdp_mgr, "set_emode_category", (1u8, "USD".to_string(), dec!("0.9"), dec!("0.93"), dec!("0.01"), 0u64)),
+        "Lowering the parameters of an e-mode category must be ramped."
+    );
+    env.govern(cdp_mgr, "set_emode_category", (1u8, "USD".to_string(), dec!("0.9"), dec!("0.93"), dec!("0.01"), 100u64)).expect_commit_success();
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(cdp_mgr, "get_emode_category", manifest_args!(1u8))
+        .build();
+    let category = env.execute(manifest, &[]).expect_commit_success().output::<Option<EModeCategory>>(1).unwrap();
+    assert_eq!(category.ltv, dec!("0.9"));
+    assert!(category.ltv_ramp.is_some() && category.liquidation_threshold_ramp.is_some());
+
+    expect_failure(env.govern(cdp_mgr, "set_asset_emode_category", (usdc, 0u8, 0u64)), "Leaving an e-mode category must be ramped.");
+    env.govern(cdp_mgr, "set_asset_emode_category", (usdc, 0u8, 100u64)).expect_commit_success();
+    assert_eq!(env.market(usdc).emode_category, 0u8);
+}
+
+#[test]
+fn test_pair_config(){
+    let mut env = Env::new();
+    let (usdt, usdc, cdp_mgr) = (env.usdt, env.usdc, env.cdp_mgr);
+    env.govern(cdp_mgr, "set_pair_config", (XRD, usdt, true, dec!("0.4"), dec!("0.5"), dec!("30"))).expect_commit_success();
+    env.govern(cdp_mgr, "set_pair_config", (XRD, usdc, false, dec!("0.4"), dec!("0.5"), Decimal::ZERO)).expect_commit_success();
+    let lender = env.new_account(dec!("1000"), dec!("1000"));
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    let other_borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdc, dec!("1000")).expect_commit_success();
+
+    // the LTV of the pair overrides the 0.6 of XRD, 1000 XRD back 20 USDT
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_failure();
+    env.open_xrd_cdp(&lender, &borrower, dec!("20"), false).expect_commit_success();
+    expect_failure(env.open_xrd_cdp(&lender, &other_borrower, dec!("15"), false), "The debt ceiling of the pair has been reached.");
+    env.open_xrd_cdp(&lender, &other_borrower, dec!("10"), false).expect_commit_success();
+
+    let prices = env.prices(usdc, XRD_PRICE);
+    expect_failure(
+        env.borrow(&other_borrower, XRD, dec!("1000"), usdc, dec!("10"), false, &prices),
+        "Borrowing the asset against the collateral is disabled."
+    );
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use scrypto::prelude::*;
+use transaction::builder::ManifestBuilder;
+use common::*;
+
+#[test]
+fn test_open_leveraged(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.supply(&lender, usdt, dec!("1000")).expect_commit_success();
+    // 1 USDT = 20 XRD in the pool as well as in the oracle
+    let swap_adapter = env.new_swap_adapter(usdt, dec!("5000"), dec!("250"), true);
+    let unapproved_adapter = env.new_swap_adapter(usdt, dec!("5000"), dec!("250"), false);
+
+    let prices = env.prices(usdt, XRD_PRICE);
+    expect_failure(
+        env.open_leveraged(&borrower, XRD, dec!("1000"), usdt, dec!("2"), unapproved_adapter, dec!("800"), &prices),
+        "The swap adapter is not approved."
+    );
+    let prices = env.prices(usdt, XRD_PRICE);
+    expect_failure(
+        env.open_leveraged(&borrower, XRD, dec!("1000"), usdt, dec!("2"), swap_adapter, dec!("900"), &prices),
+        "The swap output is less than the minimum."
+    );
+
+    // the flashloan of 50 USDT buys about 831 XRD, the debt covers the flashloan and its fee
+    let xrd_before = env.balance(&borrower, XRD);
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.open_leveraged(&borrower, XRD, dec!("1000"), usdt, dec!("2"), swap_adapter, dec!("800"), &prices).expect_commit_success();
+    assert_eq!(xrd_before - env.balance(&borrower, XRD), dec!("1000"));
+    assert_eq!(env.balance(&borrower, usdt), Decimal::ZERO);
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert!(summary.collateral_amount > dec!("1800"));
+    assert_approx(summary.debt_amount, dec!("50.05"));
+    assert!(summary.health_factor > Decimal::ONE);
+}
+
+#[test]
+fn test_repay_with_collateral(){
+    let mut env = Env::new();
+    let usdt = env.usdt;
+    let lender = env.new_account(dec!("1000"), Decimal::ZERO);
+    let borrower = env.new_account(Decimal::ZERO, Decimal::ZERO);
+    env.open_xrd_cdp(&lender, &borrower, dec!("25"), false).expect_commit_success();
+    let swap_adapter = env.new_swap_adapter(usdt, dec!("5000"), dec!("250"), true);
+
+    // the flashloan of 10 USDT repays 9.99 of the debt, the fee is kept to repay the flashloan
+    let prices = env.prices(usdt, XRD_PRICE);
+    env.repay_with_collateral(&borrower, 1u64, dec!("300"), swap_adapter, dec!("10"), &prices).expect_commit_success();
+    let summary = env.cdp_summary(1u64, price_in_xrd(XRD_PRICE), Decimal::ONE);
+    assert_approx(summary.collateral_amount, dec!("700"));
+    assert_approx(summary.debt_amount, dec!("15.01"));
+
+    // the 300 XRD are sold for 250 * 299.1 / 5299.1 USDT, what is left after the flashloan goes to the borrower
+    let swap_output = dec!("250") * dec!("299.1") / dec!("5299.1");
+    assert_approx(env.balance(&borrower, usdt), dec!("25") + swap_output - dec!("10"));
+
+    let prices = env.prices(usdt, XRD_PRICE);
+    expect_failure(
+        env.repay_with_collateral(&borrower, 1u64, dec!("100"), swap_adapter, dec!("10"), &prices),
+        "The swap output is less than the minimum."
+    );
+}
+
+#[test]
+fn test_mock_swap_adapter(){
+    let mut env = Env::new();
+    let (usdt, usdc, protocol) = (env.usdt, env.usdc, env.protocol);
+    let admin = env.admin.clone();
+    let swap_adapter = env.new_swap_adapter(usdt, dec!("5000"), dec!("250"), true);
+    let unapproved_adapter = env.new_swap_adapter(usdt, dec!("5000"), dec!("250"), false);
+
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(swap_adapter, "quote", manifest_args!(XRD, dec!("100"), usdt))
+        .build();
+    let quote = env.execute(manifest, &[]).expect_commit_success().output::<Decimal>(1);
+    assert_eq!(quote, dec!("250") * dec!("99.7") / dec!("5099.7"));
+
+    // the protocol quotes through the approved adapters only
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(protocol, "quote_swap", manifest_args!(swap_adapter, XRD, dec!("100"), usdt))
+        .build();
+    assert_eq!(env.execute(manifest, &[]).expect_commit_success().output::<Decimal>(1), quote);
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(protocol, "quote_swap", manifest_args!(unapproved_adapter, XRD, dec!("100"), usdt))
+        .build();
+    expect_failure(env.execute(manifest, &[]), "The swap adapter is not approved.");
+
+    // the swap pays out the quote and keeps the constant product
+    let usdt_before = env.balance(&admin, usdt);
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .withdraw_from_account(admin.address, XRD, dec!("100"))
+        .take_all_from_worktop(XRD, "xrd")
+        .call_method_with_name_lookup(swap_adapter, "swap", |lookup| manifest_args!(lookup.bucket("xrd"), usdt, quote))
+        .call_method(admin.address, "deposit_batch", manifest_args!(ManifestExpression::EntireWorktop))
+        .build();
+    env.execute(manifest, &[&admin]).expect_commit_success();
+    assert_eq!(env.balance(&admin, usdt) - usdt_before, quote);
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(swap_adapter, "get_reserves", manifest_args!())
+        .build();
+    let (_, xrd_reserve, _, usdt_reserve) = env.execute(manifest, &[]).expect_commit_success().output::<(ResourceAddress, Decimal, ResourceAddress, Decimal)>(1);
+    assert_eq!(xrd_reserve, dec!("5100"));
+    assert_eq!(usdt_reserve, dec!("250") - quote);
+
+    let manifest = ManifestBuilder::new()
+        .lock_fee_from_faucet()
+        .call_method(swap_adapter, "quote", manifest_args!(XRD, dec!("100"), usdc))
+        .build();
+    expect_failure(env.execute(manifest, &[]), "The pair is not supported.");
+}